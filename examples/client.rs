@@ -0,0 +1,118 @@
+//! A client for `tileworld --serve`: plays an agent by walking to the nearest
+//! tile it can see and then to the nearest hole, or watches the game.
+//!
+//! ```text
+//! cargo run -- --serve 127.0.0.1:7878
+//! cargo run --example client -- 127.0.0.1:7878 agent 2
+//! cargo run --example client -- 127.0.0.1:7878 spectator
+//! ```
+
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    net::TcpStream,
+    process::exit,
+};
+use tileworld::{
+    location::{DIRECTIONS, Direction},
+    objects::Action,
+    protocol::{Cell, Hello, Percept, Reply, Update, action_line},
+};
+
+fn main() -> io::Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let hello = match args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        [_, "agent", id] => Hello::Agent {
+            id: id.parse().unwrap_or_else(|_| usage()),
+        },
+        [_, "spectator"] => Hello::Spectator,
+        _ => usage(),
+    };
+    let mut stream = TcpStream::connect(&args[0])?;
+    let mut lines = BufReader::new(stream.try_clone()?).lines();
+    writeln!(stream, "{}", serde_json::to_string(&hello)?)?;
+    let reply: Reply = serde_json::from_str(&lines.next().unwrap_or(Ok(String::new()))?)?;
+    if let Reply::Error { message } = reply {
+        eprintln!("{message}");
+        exit(1);
+    }
+    for line in lines {
+        let line = line?;
+        if let Hello::Agent { .. } = hello {
+            let percept: Percept = serde_json::from_str(&line)?;
+            stream.write_all(action_line(act(&percept)).as_bytes())?;
+        } else {
+            match serde_json::from_str(&line)? {
+                Update::Snapshot(world) => {
+                    println!(
+                        "episode {}, tick {}: {} agents",
+                        world.episode,
+                        world.tick,
+                        world.agents.len()
+                    )
+                }
+                Update::Diff(diff) => {
+                    for a in diff.agents {
+                        println!(
+                            "tick {}: agent {} at {:?}, score {}",
+                            diff.tick, a.id, a.location, a.score
+                        );
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn usage() -> ! {
+    eprintln!("Usage: client <ADDRESS> agent <ID> | client <ADDRESS> spectator");
+    exit(2);
+}
+
+fn act(percept: &Percept) -> Action {
+    let here = percept.location;
+    let targets: Vec<Cell> = if percept.has_tile {
+        percept.holes.clone()
+    } else {
+        percept.tiles.iter().map(|t| t.location).collect()
+    };
+    if targets.contains(&here) {
+        return if percept.has_tile {
+            Action::Drop
+        } else {
+            Action::Pick
+        };
+    }
+    let Some(&target) = targets.iter().min_by_key(|&&c| distance(c, here)) else {
+        return Action::Stay;
+    };
+    let blocked: Vec<Cell> = percept
+        .obstacles
+        .iter()
+        .copied()
+        .chain(percept.agents.iter().map(|a| a.location))
+        .collect();
+    // first the steps that get closer, then any other step that's free
+    let mut steps: Vec<Direction> = DIRECTIONS.to_vec();
+    steps.sort_by_key(|&d| next(here, d).map_or(u16::MAX, |c| distance(c, target)));
+    steps
+        .into_iter()
+        .find(|&d| next(here, d).is_some_and(|c| !blocked.contains(&c)))
+        .map_or(Action::Stay, Action::Move)
+}
+
+fn distance(a: Cell, b: Cell) -> u16 {
+    a[0].abs_diff(b[0]) + a[1].abs_diff(b[1])
+}
+
+/// The cell one step away, if it is on the grid
+fn next(c: Cell, d: Direction) -> Option<Cell> {
+    let (col, row) = (c[0], c[1]);
+    match d {
+        Direction::Up => row.checked_sub(1).map(|r| [col, r]),
+        Direction::Down => (row + 1 < tileworld::ROWS).then_some([col, row + 1]),
+        Direction::Left => col.checked_sub(1).map(|c| [c, row]),
+        Direction::Right => (col + 1 < tileworld::COLS).then_some([col + 1, row]),
+        _ => None,
+    }
+}
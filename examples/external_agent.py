@@ -0,0 +1,39 @@
+#!/usr/bin/env python3
+"""A TileWorld agent in Python: walks to the nearest tile it can see, then to
+the nearest hole. Run it with
+
+    cargo run -- --external "python3 examples/external_agent.py"
+"""
+import json
+import random
+import sys
+
+DIRECTIONS = {(0, -1): "up", (0, 1): "down", (-1, 0): "left", (1, 0): "right"}
+
+
+def sign(x):
+    return (x > 0) - (x < 0)
+
+
+def act(percept):
+    col, row = percept["location"]
+    if percept["has_tile"]:
+        targets = percept["holes"]
+    else:
+        targets = [t["location"] for t in percept["tiles"]]
+    if [col, row] in targets:
+        return {"action": "drop" if percept["has_tile"] else "pick"}
+    if not targets:
+        return {"action": "move", "direction": random.choice(list(DIRECTIONS.values()))}
+    tc, tr = min(targets, key=lambda t: abs(t[0] - col) + abs(t[1] - row))
+    blocked = {tuple(o) for o in percept["obstacles"]}
+    blocked |= {tuple(a["location"]) for a in percept["agents"]}
+    steps = [(sign(tc - col), 0), (0, sign(tr - row))]
+    for dc, dr in steps + list(DIRECTIONS):
+        if (dc, dr) in DIRECTIONS and (col + dc, row + dr) not in blocked:
+            return {"action": "move", "direction": DIRECTIONS[(dc, dr)]}
+    return {"action": "wait"}
+
+
+for line in sys.stdin:
+    print(json.dumps(act(json.loads(line))), flush=True)
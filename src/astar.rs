@@ -23,7 +23,37 @@ fn reconstruct_path(
     path
 }
 
-pub(crate) fn astar(grid: &Grid, from: Location, to: Location) -> Option<Vec<Direction>> {
+/// The shortest path around everything on the grid
+pub fn astar(grid: &Grid, from: Location, to: Location) -> Option<Vec<Direction>> {
+    astar_with(grid, from, to, |l| !grid.is_free(l))
+}
+
+/// A* over the grid's geometry, but with the caller deciding which cells are
+/// in the way. Agents with a memory use this to plan on what they believe.
+pub fn astar_with<F>(
+    grid: &Grid,
+    from: Location,
+    to: Location,
+    is_blocked: F,
+) -> Option<Vec<Direction>>
+where
+    F: Fn(Location) -> bool,
+{
+    astar_traced(grid, from, to, is_blocked, &mut Vec::new())
+}
+
+/// Like `astar_with`, collecting the cells the search expanded into
+/// `expanded`, in order, for showing how the search went
+pub(crate) fn astar_traced<F>(
+    grid: &Grid,
+    from: Location,
+    to: Location,
+    is_blocked: F,
+    expanded: &mut Vec<Location>,
+) -> Option<Vec<Direction>>
+where
+    F: Fn(Location) -> bool,
+{
     // Early return if start == goal
     if from == to {
         return Some(Vec::new());
@@ -31,17 +61,18 @@ pub(crate) fn astar(grid: &Grid, from: Location, to: Location) -> Option<Vec<Dir
 
     // Priority queue: (Reverse(f_score), g_score, location)
     // Using Reverse for min-heap behavior
-    let mut open_heap: BinaryHeap<(Reverse<u16>, u16, Location)> = BinaryHeap::new();
+    let mut open_heap: BinaryHeap<(Reverse<u32>, u32, Location)> = BinaryHeap::new();
 
     // Maps location -> best known g_score (O(1) lookup)
-    let mut g_scores: HashMap<Location, u16> = HashMap::new();
+    let mut g_scores: HashMap<Location, u32> = HashMap::new();
 
     // Maps location -> (parent_location, direction_taken)
     let mut came_from: HashMap<Location, (Location, Direction)> = HashMap::new();
 
     // Initialize with start node
     g_scores.insert(from, 0);
-    let h = from.distance(to);
+    // The movement estimate is admissible because no terrain is cheaper than road
+    let h = grid.estimate(from, to);
     open_heap.push((Reverse(h), 0, from));
 
     while let Some((_, current_g, current_loc)) = open_heap.pop() {
@@ -51,25 +82,21 @@ pub(crate) fn astar(grid: &Grid, from: Location, to: Location) -> Option<Vec<Dir
         }
 
         // Skip if we've already found a better path to this node
-        if let Some(&best_g) = g_scores.get(&current_loc) {
-            if current_g > best_g {
-                continue;
-            }
+        if let Some(&best_g) = g_scores.get(&current_loc)
+            && current_g > best_g
+        {
+            continue;
         }
+        expanded.push(current_loc);
 
         // Explore neighbors
-        for d in [
-            Direction::Up,
-            Direction::Down,
-            Direction::Left,
-            Direction::Right,
-        ] {
-            if current_loc.is_valid_move(d) {
-                let next_loc = current_loc.next_location(d);
+        for &d in grid.movement().directions() {
+            if grid.can_move(current_loc, d) {
+                let next_loc = grid.next_location(current_loc, d);
 
                 // Check if passable (or is the destination)
-                if next_loc == to || grid.is_free(next_loc) {
-                    let tentative_g = current_g + 1;
+                if next_loc == to || !is_blocked(next_loc) {
+                    let tentative_g = current_g + grid.step_cost(current_loc, d);
 
                     // Only proceed if this is a better path
                     let dominated = g_scores
@@ -80,7 +107,7 @@ pub(crate) fn astar(grid: &Grid, from: Location, to: Location) -> Option<Vec<Dir
                         g_scores.insert(next_loc, tentative_g);
                         came_from.insert(next_loc, (current_loc, d));
 
-                        let h = next_loc.distance(to);
+                        let h = grid.estimate(next_loc, to);
                         let f = tentative_g + h;
                         open_heap.push((Reverse(f), tentative_g, next_loc));
                     }
@@ -95,6 +122,11 @@ pub(crate) fn astar(grid: &Grid, from: Location, to: Location) -> Option<Vec<Dir
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{
+        COLS,
+        location::{Movement, Topology},
+        terrain::Terrain,
+    };
 
     /// Helper to verify a path leads from start to end
     fn verify_path(start: Location, end: Location, path: &[Direction]) -> bool {
@@ -200,6 +232,79 @@ mod tests {
         assert!(verify_path(from, to, &p));
     }
 
+    #[test]
+    fn test_path_avoids_expensive_terrain() {
+        let mut grid = Grid::new();
+        let from = Location::new(0, 1);
+        let to = Location::new(4, 1);
+        // a strip of water on the direct route, road one row up
+        for c in 1..4 {
+            grid.set_terrain(Location::new(c, 1), Terrain::Water);
+        }
+        let p = astar(&grid, from, to).unwrap();
+        assert_eq!(p.len(), 6);
+        assert!(verify_path(from, to, &p));
+        let mut current = from;
+        for &d in &p {
+            current = current.next_location(d);
+            assert_ne!(grid.terrain(current), Terrain::Water);
+        }
+    }
+
+    #[test]
+    fn test_path_crosses_cheap_terrain() {
+        let mut grid = Grid::new();
+        let from = Location::new(0, 1);
+        let to = Location::new(2, 1);
+        // a single grass cell costs less than going around
+        grid.set_terrain(Location::new(1, 1), Terrain::Grass);
+        let p = astar(&grid, from, to).unwrap();
+        assert_eq!(p, vec![Direction::Right, Direction::Right]);
+    }
+
+    #[test]
+    fn test_path_eight_directions() {
+        let mut grid = Grid::new();
+        grid.set_movement(Movement::Eight);
+        let from = Location::new(0, 0);
+        let to = Location::new(3, 5);
+        let p = astar(&grid, from, to).unwrap();
+        assert_eq!(p.len(), 5); // Chebyshev distance
+        assert!(verify_path(from, to, &p));
+    }
+
+    #[test]
+    fn test_path_octile_prefers_straight_steps() {
+        let mut grid = Grid::new();
+        grid.set_movement(Movement::Octile);
+        let from = Location::new(0, 0);
+        let to = Location::new(4, 0);
+        let p = astar(&grid, from, to).unwrap();
+        // zig-zagging diagonally is just as short in steps but more expensive
+        assert_eq!(p, vec![Direction::Right; 4]);
+    }
+
+    #[test]
+    fn test_path_does_not_cut_corners() {
+        let mut grid = Grid::new();
+        grid.set_movement(Movement::Eight);
+        let from = Location::new(0, 0);
+        let to = Location::new(1, 1);
+        grid.add_obstacle(Location::new(1, 0));
+        let p = astar(&grid, from, to).unwrap();
+        assert_eq!(p, vec![Direction::Down, Direction::Right]);
+    }
+
+    #[test]
+    fn test_path_wraps_around_torus() {
+        let mut grid = Grid::new();
+        grid.set_topology(Topology::Torus);
+        let from = Location::new(0, 3);
+        let to = Location::new(COLS - 2, 3);
+        let p = astar(&grid, from, to).unwrap();
+        assert_eq!(p, vec![Direction::Left; 2]);
+    }
+
     #[test]
     fn test_can_not_reach() {
         let grid = Grid::new();
@@ -208,4 +313,17 @@ mod tests {
         let path = astar(&grid, from, to);
         assert!(path.is_none());
     }
+
+    #[test]
+    fn test_traced_search() {
+        let grid = Grid::new();
+        let from = Location::new(5, 5);
+        let to = Location::new(5, 9);
+        let mut expanded = vec![];
+        let p = astar_traced(&grid, from, to, |l| !grid.is_free(l), &mut expanded).unwrap();
+        assert_eq!(p.len(), 4);
+        assert_eq!(expanded.first(), Some(&from));
+        // straight towards the goal on an empty grid
+        assert!(expanded.len() >= 4 && expanded.len() < 20);
+    }
 }
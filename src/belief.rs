@@ -0,0 +1,257 @@
+use crate::{
+    COLS, ROWS,
+    grid::Grid,
+    location::Location,
+    objects::{GO, Object, Sighting},
+    sensor::Sensor,
+};
+use rand::Rng;
+use std::{collections::HashMap, rc::Rc};
+
+/// What an agent saw in a cell
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Seen {
+    Free,
+    Obstacle,
+    Tile(u32),
+    Hole,
+    Agent(u8),
+}
+
+/// An agent's memory of the grid: the last seen contents of every cell it
+/// has seen, and where it last saw each tile and hole
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct BeliefMap {
+    cells: HashMap<Location, (Seen, u64)>,
+    tiles: Vec<Sighting>,
+    holes: Vec<Sighting>,
+}
+
+impl BeliefMap {
+    /// Record everything the agent at `from` can currently perceive
+    pub fn observe(&mut self, g: &Grid, from: Location, tiles: &[Object], holes: &[Object]) {
+        let tick = g.tick();
+        let sensor = g.sensor();
+        let visible: Vec<Location> = cells_around(from, sensor)
+            .filter(|&l| sensor.can_see(g, from, l))
+            .collect();
+        for &l in &visible {
+            // the agent itself is borrowed while it observes, it doesn't need to see itself
+            let seen = match g.object(l) {
+                Some(o) if l != from => match *o.borrow() {
+                    GO::Obstacle(_) => Seen::Obstacle,
+                    GO::Agent(ref a) => Seen::Agent(a.id),
+                    // tiles and holes are taken from their lists below, an
+                    // agent standing on one hides it on the grid
+                    _ => Seen::Free,
+                },
+                _ => Seen::Free,
+            };
+            self.cells.insert(l, (seen, tick));
+        }
+        // forget tiles and holes that should be in view but aren't there anymore
+        let out_of_view = |(_, l): &Sighting| !visible.contains(l);
+        self.tiles.retain(out_of_view);
+        self.holes.retain(out_of_view);
+        for t in tiles {
+            let l = *t.borrow().location();
+            if sensor.can_see(g, from, l) {
+                self.cells.insert(l, (Seen::Tile(t.borrow().score()), tick));
+                remember(&mut self.tiles, t, l);
+            }
+        }
+        for h in holes {
+            let l = *h.borrow().location();
+            if sensor.can_see(g, from, l) {
+                self.cells.insert(l, (Seen::Hole, tick));
+                remember(&mut self.holes, h, l);
+            }
+        }
+    }
+
+    /// Record a tile another agent reported at `location` at `tick`, unless
+    /// this agent has seen that cell since
+    pub fn hear_tile(&mut self, tile: &Object, location: Location, tick: u64) {
+        if self.is_newer(location, tick) {
+            self.cells
+                .insert(location, (Seen::Tile(tile.borrow().score()), tick));
+            remember(&mut self.tiles, tile, location);
+        }
+    }
+
+    /// Like `hear_tile`, for a hole
+    pub fn hear_hole(&mut self, hole: &Object, location: Location, tick: u64) {
+        if self.is_newer(location, tick) {
+            self.cells.insert(location, (Seen::Hole, tick));
+            remember(&mut self.holes, hole, location);
+        }
+    }
+
+    /// Whether news from `tick` about `location` is newer than what is known
+    fn is_newer(&self, location: Location, tick: u64) -> bool {
+        self.cells
+            .get(&location)
+            .is_none_or(|&(_, seen_at)| seen_at < tick)
+    }
+
+    /// Record that the agent tried to step into `location` at `tick` and found
+    /// it blocked. It stays blocked until the agent sees the cell again.
+    pub fn bump(&mut self, location: Location, tick: u64) {
+        if !matches!(
+            self.cells.get(&location),
+            Some((Seen::Tile(_) | Seen::Hole, _))
+        ) {
+            self.cells.insert(location, (Seen::Obstacle, tick));
+        }
+    }
+
+    /// Tiles where they were last seen
+    pub fn tiles(&self) -> &[Sighting] {
+        &self.tiles
+    }
+
+    /// Holes where they were last seen
+    pub fn holes(&self) -> &[Sighting] {
+        &self.holes
+    }
+
+    /// What was last seen in `location` and when, None if it was never seen
+    pub fn cell(&self, location: Location) -> Option<(Seen, u64)> {
+        self.cells.get(&location).copied()
+    }
+
+    /// Whether the path planner should route around `location`. Unknown cells
+    /// are assumed to be free, other agents only block while they are in view.
+    pub fn is_blocked(&self, location: Location, tick: u64) -> bool {
+        match self.cells.get(&location) {
+            Some((Seen::Obstacle | Seen::Tile(_) | Seen::Hole, _)) => true,
+            Some((Seen::Agent(_), seen_at)) => *seen_at == tick,
+            _ => false,
+        }
+    }
+
+    /// A location worth exploring: of a handful of random free locations, the
+    /// one that was seen longest ago (or never)
+    pub fn exploration_target(&self, g: &Grid) -> Option<Location> {
+        (0..10)
+            .filter_map(|_| {
+                let mut rng = g.rng();
+                let l = Location::new(rng.random_range(0..COLS), rng.random_range(0..ROWS));
+                drop(rng);
+                g.is_free(l).then_some(l)
+            })
+            .min_by_key(|l| self.cells.get(l).map(|&(_, tick)| tick + 1).unwrap_or(0))
+    }
+}
+
+/// Replace whatever was remembered about `o` with its new location
+fn remember(sightings: &mut Vec<Sighting>, o: &Object, l: Location) {
+    sightings.retain(|(known, _)| !Rc::ptr_eq(known, o));
+    sightings.push((o.clone(), l));
+}
+
+/// The cells a sensor could possibly cover from `from`
+fn cells_around(from: Location, sensor: Sensor) -> Box<dyn Iterator<Item = Location>> {
+    let all = (0..ROWS).flat_map(|r| (0..COLS).map(move |c| Location::new(c, r)));
+    match sensor {
+        Sensor::Radius { radius, .. } if radius < COLS.min(ROWS) / 2 => {
+            // a square around the agent, wrapping around the edges on a torus
+            // and clipped again by the sensor itself otherwise
+            let offsets = -i32::from(radius)..=i32::from(radius);
+            Box::new(offsets.clone().flat_map(move |dr| {
+                offsets.clone().map(move |dc| {
+                    let c = (i32::from(from.col) + dc).rem_euclid(i32::from(COLS));
+                    let r = (i32::from(from.row) + dr).rem_euclid(i32::from(ROWS));
+                    Location::new(c as u16, r as u16)
+                })
+            }))
+        }
+        _ => Box::new(all),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sensing_grid(radius: u16) -> Grid {
+        let mut grid = Grid::new();
+        grid.set_sensor(Sensor::Radius {
+            radius,
+            line_of_sight: false,
+        });
+        grid.set_memory(true);
+        grid
+    }
+
+    #[test]
+    fn test_observe_remembers_cells_in_range() {
+        let mut grid = sensing_grid(2);
+        grid.add_obstacle(Location::new(6, 5));
+        let mut belief = BeliefMap::default();
+        let from = Location::new(5, 5);
+        belief.observe(&grid, from, &[], &[]);
+        assert_eq!(belief.cell(from), Some((Seen::Free, 0)));
+        assert_eq!(belief.cell(Location::new(6, 5)), Some((Seen::Obstacle, 0)));
+        assert_eq!(belief.cell(Location::new(9, 5)), None);
+        assert!(belief.is_blocked(Location::new(6, 5), 0));
+        assert!(!belief.is_blocked(Location::new(9, 5), 0));
+    }
+
+    #[test]
+    fn test_remembers_tiles_out_of_sight() {
+        let mut grid = sensing_grid(2);
+        let (agents, tiles, _) = grid.create_objects(0, 1, 0, 0);
+        assert!(agents.is_empty());
+        let tile_loc = *tiles[0].borrow().location();
+        let mut belief = BeliefMap::default();
+
+        belief.observe(&grid, tile_loc, &tiles, &[]);
+        assert_eq!(belief.tiles().len(), 1);
+        assert_eq!(belief.tiles()[0].1, tile_loc);
+
+        // the tile moves away while nobody is looking
+        let new_loc = Location::new((tile_loc.col + 20) % COLS, tile_loc.row);
+        grid.move_object(tiles[0].clone(), tile_loc, new_loc);
+        tiles[0].borrow_mut().set_location(new_loc);
+        let far = Location::new((tile_loc.col + 10) % COLS, (tile_loc.row + 20) % ROWS);
+        belief.observe(&grid, far, &tiles, &[]);
+        assert_eq!(belief.tiles()[0].1, tile_loc);
+
+        // coming back shows it is gone
+        belief.observe(&grid, tile_loc, &tiles, &[]);
+        assert!(belief.tiles().is_empty());
+        assert_eq!(
+            belief.cell(tile_loc).map(|(seen, _)| seen),
+            Some(Seen::Free)
+        );
+    }
+
+    #[test]
+    fn test_hear_only_newer_news() {
+        let mut grid = sensing_grid(2);
+        let (_, tiles, _) = grid.create_objects(0, 1, 0, 0);
+        let tile_loc = *tiles[0].borrow().location();
+        let mut belief = BeliefMap::default();
+        belief.hear_tile(&tiles[0], tile_loc, 5);
+        assert_eq!(belief.tiles().len(), 1);
+
+        // seen empty later on, an old report doesn't bring the tile back
+        let mut belief = BeliefMap::default();
+        for _ in 0..10 {
+            grid.update(&[], &[], &[]);
+        }
+        belief.observe(&grid, tile_loc, &[], &[]);
+        belief.hear_tile(&tiles[0], tile_loc, 5);
+        assert!(belief.tiles().is_empty());
+    }
+
+    #[test]
+    fn test_bump_marks_unknown_cell_blocked() {
+        let mut belief = BeliefMap::default();
+        let l = Location::new(3, 3);
+        assert!(!belief.is_blocked(l, 7));
+        belief.bump(l, 7);
+        assert!(belief.is_blocked(l, 7));
+    }
+}
@@ -0,0 +1,97 @@
+use piston::Key;
+
+/// Update rates to choose from, in ticks per second
+pub const SPEEDS: [u64; 9] = [1, 2, 4, 7, 10, 15, 30, 60, 120];
+
+/// Pauses, single-steps and sets the speed of the simulation in the window.
+/// Space pauses and resumes, N advances one tick while paused, + and - speed
+/// up and slow down.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Clock {
+    paused: bool,
+    step: bool,
+    speed: usize,
+}
+
+impl Clock {
+    /// A running clock at the speed closest to `ups`
+    pub fn new(ups: u64) -> Self {
+        let speed = (0..SPEEDS.len())
+            .min_by_key(|&i| SPEEDS[i].abs_diff(ups))
+            .unwrap_or_default();
+        Clock {
+            paused: false,
+            step: false,
+            speed,
+        }
+    }
+
+    /// Ticks per second
+    pub fn ups(&self) -> u64 {
+        SPEEDS[self.speed]
+    }
+
+    pub fn paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Returns whether the key is one of the clock's
+    pub fn press(&mut self, key: Key) -> bool {
+        match key {
+            Key::Space => self.paused = !self.paused,
+            Key::N if self.paused => self.step = true,
+            Key::Equals | Key::Plus | Key::NumPadPlus => {
+                self.speed = (self.speed + 1).min(SPEEDS.len() - 1)
+            }
+            Key::Minus | Key::NumPadMinus => self.speed = self.speed.saturating_sub(1),
+            _ => return false,
+        }
+        true
+    }
+
+    /// Whether the simulation advances on this update
+    pub fn tick(&mut self) -> bool {
+        !self.paused || std::mem::take(&mut self.step)
+    }
+
+    /// For the sidebar
+    pub fn status(&self) -> String {
+        if self.paused {
+            "Paused (Space resumes, N steps)".to_string()
+        } else {
+            format!("Speed: {} ticks/s", self.ups())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pause_and_step() {
+        let mut clock = Clock::new(7);
+        assert!(clock.tick());
+        assert!(!clock.press(Key::N));
+        clock.press(Key::Space);
+        assert!(!clock.tick());
+        clock.press(Key::N);
+        assert!(clock.tick());
+        assert!(!clock.tick());
+        clock.press(Key::Space);
+        assert!(clock.tick());
+    }
+
+    #[test]
+    fn test_speed() {
+        let mut clock = Clock::new(8);
+        assert_eq!(clock.ups(), 7);
+        clock.press(Key::Equals);
+        assert_eq!(clock.ups(), 10);
+        for _ in 0..10 {
+            clock.press(Key::Minus);
+        }
+        assert_eq!(clock.ups(), 1);
+        assert_eq!(clock.status(), "Speed: 1 ticks/s");
+    }
+}
@@ -0,0 +1,341 @@
+use crate::{
+    location::{Location, Topology},
+    objects::Object,
+};
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use std::{collections::HashMap, rc::Rc};
+
+/// What agents tell each other
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Content {
+    /// A tile was seen at a location
+    TileAt(Object, Location),
+    /// A hole was seen at a location
+    HoleAt(Object, Location),
+    /// The sender is heading for the tile or hole at a location, others
+    /// should pick something else
+    Claim(Object, Location),
+}
+
+/// Who a message is for. Agents claim targets within their own team, so in a
+/// free-for-all, where every agent is a team of its own, nobody hears of
+/// them. What they see goes to everybody unless there are teams.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Recipient {
+    /// Every agent but the sender
+    Broadcast,
+    /// Every agent on a team but the sender
+    Team(u8),
+    Agent(u8),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    pub from: u8,
+    pub to: Recipient,
+    pub content: Content,
+    pub sent_at: u64,
+}
+
+/// Limits of the channel between agents
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CommsConfig {
+    /// Maximum distance between sender and receiver, None for unlimited
+    pub range: Option<u16>,
+    /// Ticks between sending and delivery
+    pub latency: u64,
+    /// Messages each agent may send per tick, None for unlimited
+    pub bandwidth: Option<u32>,
+    /// Chance in percent that a message does not arrive
+    pub loss: u8,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CommsStats {
+    pub sent: u64,
+    pub delivered: u64,
+    pub lost: u64,
+    pub out_of_range: u64,
+    pub over_bandwidth: u64,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct InFlight {
+    message: Message,
+    // where the sender was when it sent the message
+    origin: Location,
+    // agents that already got (or lost) the message
+    handled: Vec<u8>,
+}
+
+/// Carries messages between agents, subject to the configured range,
+/// latency, bandwidth and loss
+#[derive(Debug, PartialEq, Eq)]
+pub struct MessageBus {
+    config: CommsConfig,
+    in_flight: Vec<InFlight>,
+    // messages sent by each agent in the current tick
+    sent_this_tick: HashMap<u8, u32>,
+    stats: CommsStats,
+    // decides which messages get lost
+    rng: StdRng,
+}
+
+impl MessageBus {
+    pub fn new(config: CommsConfig, seed: u64) -> Self {
+        MessageBus {
+            config,
+            in_flight: vec![],
+            sent_this_tick: HashMap::new(),
+            stats: CommsStats::default(),
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    pub fn stats(&self) -> CommsStats {
+        self.stats
+    }
+
+    /// Start a new tick: reset the bandwidth budget and drop messages every
+    /// agent has had a chance to receive
+    pub fn start_tick(&mut self, tick: u64) {
+        self.sent_this_tick.clear();
+        let latency = self.config.latency;
+        // agents updated before the sender only see a message the next tick
+        self.in_flight
+            .retain(|m| tick < m.message.sent_at + latency + 2);
+    }
+
+    /// Queue a message from an agent at `origin`. Returns false if the sender
+    /// used up its bandwidth for this tick.
+    pub fn send(&mut self, message: Message, origin: Location) -> bool {
+        let sent = self.sent_this_tick.entry(message.from).or_insert(0);
+        if self.config.bandwidth.is_some_and(|b| *sent >= b) {
+            self.stats.over_bandwidth += 1;
+            return false;
+        }
+        *sent += 1;
+        self.stats.sent += 1;
+        self.in_flight.push(InFlight {
+            message,
+            origin,
+            handled: vec![],
+        });
+        true
+    }
+
+    /// Messages for agent `id` of `team` at `location` that have arrived by `tick`
+    pub fn receive(
+        &mut self,
+        id: u8,
+        team: u8,
+        location: Location,
+        tick: u64,
+        topology: Topology,
+    ) -> Vec<Message> {
+        let mut inbox = vec![];
+        for m in &mut self.in_flight {
+            let addressed = match m.message.to {
+                Recipient::Broadcast => m.message.from != id,
+                Recipient::Team(to) => to == team && m.message.from != id,
+                Recipient::Agent(to) => to == id,
+            };
+            if !addressed
+                || m.handled.contains(&id)
+                || tick < m.message.sent_at + self.config.latency
+            {
+                continue;
+            }
+            m.handled.push(id);
+            if let Some(range) = self.config.range {
+                let (col_diff, row_diff) = topology.offset(m.origin, location);
+                let (col_diff, row_diff, range) =
+                    (u32::from(col_diff), u32::from(row_diff), u32::from(range));
+                if col_diff * col_diff + row_diff * row_diff > range * range {
+                    self.stats.out_of_range += 1;
+                    continue;
+                }
+            }
+            if self.rng.random_range(0..100) < self.config.loss {
+                self.stats.lost += 1;
+                continue;
+            }
+            self.stats.delivered += 1;
+            inbox.push(m.message.clone());
+        }
+        inbox
+    }
+}
+
+/// A claim another agent made on a tile or hole
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Claim {
+    pub object: Object,
+    pub location: Location,
+    pub by: u8,
+    pub at: u64,
+}
+
+/// Claims are forgotten after this many ticks, in case the claimer gave up
+pub const CLAIM_TIMEOUT: u64 = 100;
+
+/// Whether `object` at `location` is claimed by another agent than `id`
+pub fn claimed_by_other(
+    claims: &[Claim],
+    object: &Object,
+    location: Location,
+    id: u8,
+) -> Option<u8> {
+    claims
+        .iter()
+        .find(|c| c.by != id && c.location == location && Rc::ptr_eq(&c.object, object))
+        .map(|c| c.by)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::objects::{GO, HoleState};
+    use std::cell::RefCell;
+
+    fn hole(l: Location) -> Object {
+        Rc::new(RefCell::new(GO::Hole(HoleState { location: l })))
+    }
+
+    fn broadcast(from: u8, sent_at: u64) -> Message {
+        let l = Location::new(1, 1);
+        Message {
+            from,
+            to: Recipient::Broadcast,
+            content: Content::HoleAt(hole(l), l),
+            sent_at,
+        }
+    }
+
+    #[test]
+    fn test_broadcast_reaches_everyone_but_sender_once() {
+        let mut bus = MessageBus::new(CommsConfig::default(), 0);
+        let here = Location::new(0, 0);
+        bus.start_tick(1);
+        assert!(bus.send(broadcast(1, 1), here));
+        assert!(bus.receive(1, 1, here, 1, Topology::Bounded).is_empty());
+        assert_eq!(bus.receive(2, 1, here, 1, Topology::Bounded).len(), 1);
+        assert!(bus.receive(2, 1, here, 1, Topology::Bounded).is_empty());
+        assert_eq!(bus.receive(3, 2, here, 1, Topology::Bounded).len(), 1);
+        assert_eq!(bus.stats().delivered, 2);
+    }
+
+    #[test]
+    fn test_direct_message() {
+        let mut bus = MessageBus::new(CommsConfig::default(), 0);
+        let here = Location::new(0, 0);
+        let mut m = broadcast(1, 0);
+        m.to = Recipient::Agent(3);
+        bus.send(m, here);
+        assert!(bus.receive(2, 1, here, 0, Topology::Bounded).is_empty());
+        assert_eq!(bus.receive(3, 1, here, 0, Topology::Bounded).len(), 1);
+    }
+
+    #[test]
+    fn test_team_message() {
+        let mut bus = MessageBus::new(CommsConfig::default(), 0);
+        let here = Location::new(0, 0);
+        let mut m = broadcast(1, 0);
+        m.to = Recipient::Team(2);
+        bus.send(m, here);
+        assert!(bus.receive(1, 2, here, 0, Topology::Bounded).is_empty());
+        assert!(bus.receive(2, 1, here, 0, Topology::Bounded).is_empty());
+        assert_eq!(bus.receive(3, 2, here, 0, Topology::Bounded).len(), 1);
+    }
+
+    #[test]
+    fn test_latency() {
+        let mut bus = MessageBus::new(
+            CommsConfig {
+                latency: 3,
+                ..CommsConfig::default()
+            },
+            0,
+        );
+        let here = Location::new(0, 0);
+        bus.send(broadcast(1, 10), here);
+        assert!(bus.receive(2, 1, here, 12, Topology::Bounded).is_empty());
+        assert_eq!(bus.receive(2, 1, here, 13, Topology::Bounded).len(), 1);
+        // expired once everybody had a chance to receive it
+        bus.start_tick(15);
+        assert!(bus.receive(3, 1, here, 15, Topology::Bounded).is_empty());
+    }
+
+    #[test]
+    fn test_range() {
+        let mut bus = MessageBus::new(
+            CommsConfig {
+                range: Some(5),
+                ..CommsConfig::default()
+            },
+            0,
+        );
+        bus.send(broadcast(1, 0), Location::new(0, 0));
+        assert!(
+            bus.receive(2, 1, Location::new(6, 0), 0, Topology::Bounded)
+                .is_empty()
+        );
+        assert_eq!(
+            bus.receive(3, 1, Location::new(3, 4), 0, Topology::Bounded)
+                .len(),
+            1
+        );
+        assert_eq!(bus.stats().out_of_range, 1);
+    }
+
+    #[test]
+    fn test_bandwidth() {
+        let mut bus = MessageBus::new(
+            CommsConfig {
+                bandwidth: Some(2),
+                ..CommsConfig::default()
+            },
+            0,
+        );
+        let here = Location::new(0, 0);
+        bus.start_tick(0);
+        assert!(bus.send(broadcast(1, 0), here));
+        assert!(bus.send(broadcast(1, 0), here));
+        assert!(!bus.send(broadcast(1, 0), here));
+        assert!(bus.send(broadcast(2, 0), here));
+        bus.start_tick(1);
+        assert!(bus.send(broadcast(1, 1), here));
+        assert_eq!(bus.stats().over_bandwidth, 1);
+    }
+
+    #[test]
+    fn test_total_loss() {
+        let mut bus = MessageBus::new(
+            CommsConfig {
+                loss: 100,
+                ..CommsConfig::default()
+            },
+            0,
+        );
+        let here = Location::new(0, 0);
+        bus.send(broadcast(1, 0), here);
+        assert!(bus.receive(2, 1, here, 0, Topology::Bounded).is_empty());
+        assert_eq!(bus.stats().lost, 1);
+    }
+
+    #[test]
+    fn test_claimed_by_other() {
+        let l = Location::new(2, 2);
+        let h = hole(l);
+        let claims = vec![Claim {
+            object: h.clone(),
+            location: l,
+            by: 2,
+            at: 0,
+        }];
+        assert_eq!(claimed_by_other(&claims, &h, l, 1), Some(2));
+        assert_eq!(claimed_by_other(&claims, &h, l, 2), None);
+        // the hole has moved since it was claimed
+        assert_eq!(claimed_by_other(&claims, &h, Location::new(3, 3), 1), None);
+    }
+}
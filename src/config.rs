@@ -0,0 +1,467 @@
+use crate::{
+    comms::CommsConfig,
+    episode::EpisodeConfig,
+    game::Game,
+    location::{Movement, Topology},
+    map::{Item, Map},
+    mapgen::Generator,
+    sensor::Sensor,
+};
+use std::{path::PathBuf, str::FromStr};
+
+/// Simulation settings, parsed from the command line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Config {
+    pub agents: u8,
+    pub tiles: u8,
+    pub holes: u8,
+    pub obstacles: u8,
+    pub wandering_obstacles: u8,
+    pub doors: u8,
+    pub door_period: u64,
+    pub blinking_obstacles: u8,
+    pub blink_period: u64,
+    pub terrain_patches: u8,
+    pub movement: Movement,
+    pub topology: Topology,
+    pub generator: Generator,
+    /// Layout to start from instead of a generated one
+    pub map: Option<Map>,
+    /// Where the map editor saves the layout
+    pub map_file: PathBuf,
+    pub sensor: Sensor,
+    pub memory: bool,
+    pub comms: Option<CommsConfig>,
+    pub game: Game,
+    pub respawn: bool,
+    pub episode: EpisodeConfig,
+    /// Makes runs reproducible, None for a different run every time
+    pub seed: Option<u64>,
+    /// Train the Q-learning agent for this many episodes without a window
+    pub train: Option<u32>,
+    /// Compare the Q-learning agent to the built-in one over this many episodes
+    pub evaluate: Option<u32>,
+    /// Where the Q-learning agent's table is kept
+    pub q_table: PathBuf,
+    /// Let the Q-learning agent play agent 1 in the window
+    pub q_agent: bool,
+    /// Play agent 1 with the keyboard
+    pub play: bool,
+    /// Commands of processes that play the next agents, see `external`
+    pub external: Vec<String>,
+    /// Milliseconds an external or remote agent gets to answer each tick
+    pub external_timeout: u64,
+    /// Address to accept remote agents and spectators on, see `server`
+    pub serve: Option<String>,
+    /// Run this many ticks without a window
+    pub headless: Option<u64>,
+    /// Show the simulation in the terminal instead of a window
+    pub tui: bool,
+    /// Where to save the heatmap of the run when it is over
+    pub heatmap: Option<PathBuf>,
+    /// Directory to save a PNG of every recorded tick in
+    pub frames: Option<PathBuf>,
+    /// Where to save the recorded ticks as an animated GIF
+    pub gif: Option<PathBuf>,
+    /// Record one tick out of this many
+    pub record_every: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            agents: 6,
+            tiles: 20,
+            holes: 20,
+            obstacles: 20,
+            wandering_obstacles: 0,
+            doors: 0,
+            door_period: 20,
+            blinking_obstacles: 0,
+            blink_period: 30,
+            terrain_patches: 0,
+            movement: Movement::Four,
+            topology: Topology::Bounded,
+            generator: Generator::Random,
+            map: None,
+            map_file: PathBuf::from("map.txt"),
+            sensor: Sensor::Omniscient,
+            memory: false,
+            comms: None,
+            game: Game::default(),
+            respawn: true,
+            episode: EpisodeConfig {
+                stuck_after: Some(100),
+                ..EpisodeConfig::default()
+            },
+            seed: None,
+            train: None,
+            evaluate: None,
+            q_table: PathBuf::from("qtable.txt"),
+            q_agent: false,
+            play: false,
+            external: vec![],
+            external_timeout: 100,
+            serve: None,
+            headless: None,
+            tui: false,
+            heatmap: None,
+            frames: None,
+            gif: None,
+            record_every: 1,
+        }
+    }
+}
+
+pub const USAGE: &str = "\
+Usage: tileworld [OPTIONS]
+
+Options:
+  --agents <N>               number of agents (default 6)
+  --tiles <N>                number of tiles (default 20)
+  --holes <N>                number of holes (default 20)
+  --obstacles <N>            number of static obstacles (default 20)
+  --wandering-obstacles <N>  obstacles that take a random step every tick (default 0)
+  --doors <N>                obstacles that open and close on a schedule (default 0)
+  --door-period <TICKS>      ticks a door stays open or closed (default 20)
+  --blinking-obstacles <N>   obstacles that vanish and reappear elsewhere (default 0)
+  --blink-period <TICKS>     ticks a blinking obstacle stays visible or hidden (default 30)
+  --terrain-patches <N>      patches of grass, mud and water that slow agents down (default 0)
+  --movement <MODEL>         four, eight (diagonals cost 1) or octile (diagonals cost 1.4) (default four)
+  --topology <TOPOLOGY>      bounded, or torus to wrap around the edges (default bounded)
+  --generator <LAYOUT>       random, maze, rooms, warehouse or caves (default random)
+  --map <FILE>               start from the layout in a map file, which the map editor
+                             also saves to (default map.txt)
+  --sensor <RANGE>           what agents perceive: all, a radius like 5, or 5:los to also
+                             let obstacles block the view (default all)
+  --memory                   agents remember what they have seen and plan on it
+  --comms                    let agents tell each other what they see and claim targets,
+                             implies --memory
+  --comms-range <CELLS>      how far messages reach (default unlimited), implies --comms
+  --comms-latency <TICKS>    delay before a message arrives (default 0), implies --comms
+  --comms-bandwidth <N>      messages an agent may send per tick (default unlimited),
+                             implies --comms
+  --comms-loss <PERCENT>     chance a message gets lost (default 0), implies --comms
+  --mode <MODE>              coop (one team), teams (two teams), teams:N, or ffa (every
+                             agent for itself) (default coop)
+  --time-limit <TICKS>       end the game and declare the winner after this many ticks
+  --target-score <N>         end the episode once a team (or agent in ffa) has this score
+  --no-respawn               used tiles and holes are gone instead of reappearing elsewhere,
+                             the episode ends when they run out
+  --stuck-after <TICKS>      end the episode when no agent moved or scored for this long,
+                             0 to never give up (default 100)
+  --auto-reset               start a new episode on a fresh grid when one ends
+  --seed <N>                 seed for the random numbers, to repeat a run exactly
+  --train <EPISODES>         train the Q-learning agent without a window, then save its table
+  --evaluate <EPISODES>      compare the Q-learning agent with the built-in one without a window
+  --q-table <FILE>           where the Q-learning table is loaded from and saved to
+                             (default qtable.txt)
+  --q-agent                  let the trained Q-learning agent play agent 1
+  --play                     play agent 1 yourself: arrows move (Q, E, Z and C diagonally),
+                             P picks up a tile, D drops it
+  --external <COMMAND>       let a process play the next agent, speaking JSON lines on
+                             stdin/stdout (repeat for more agents)
+  --external-timeout <MS>    how long an external or remote agent may think each tick
+                             (default 100)
+  --serve <ADDRESS>          let remote agents and spectators connect over TCP, like
+                             127.0.0.1:7878; remote agents can play the agents that aren't
+                             controlled otherwise
+  --headless <TICKS>         run this many ticks without a window, starting a new episode
+                             whenever one ends
+  --tui                      show the simulation in the terminal instead of a window, with
+                             the same keys to pause, step and change the speed
+  --heatmap <FILE>           save where the agents went and where they got stuck as a PNG
+                             when the run is over
+  --frames <DIR>             save a PNG of the grid and sidebar after every tick, drawn
+                             without a window or GPU
+  --gif <FILE>               record the run as an animated GIF, drawn the same way
+  --record-every <TICKS>     only save every so many ticks with --frames and --gif
+                             (default 1)
+  -h, --help                 print this help";
+
+impl Config {
+    pub fn from_args() -> Result<Config, String> {
+        Config::parse(std::env::args().skip(1))
+    }
+
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Config, String> {
+        let mut config = Config::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--agents" => config.agents = value(&arg, args.next())?,
+                "--tiles" => config.tiles = value(&arg, args.next())?,
+                "--holes" => config.holes = value(&arg, args.next())?,
+                "--obstacles" => config.obstacles = value(&arg, args.next())?,
+                "--wandering-obstacles" => config.wandering_obstacles = value(&arg, args.next())?,
+                "--doors" => config.doors = value(&arg, args.next())?,
+                "--door-period" => config.door_period = value(&arg, args.next())?,
+                "--blinking-obstacles" => config.blinking_obstacles = value(&arg, args.next())?,
+                "--blink-period" => config.blink_period = value(&arg, args.next())?,
+                "--terrain-patches" => config.terrain_patches = value(&arg, args.next())?,
+                "--movement" => config.movement = value(&arg, args.next())?,
+                "--topology" => config.topology = value(&arg, args.next())?,
+                "--generator" => config.generator = value(&arg, args.next())?,
+                "--map" => {
+                    let path: PathBuf = value(&arg, args.next())?;
+                    let map = Map::load(&path)
+                        .map_err(|e| format!("cannot load {}: {e}", path.display()))?;
+                    config.map = Some(map);
+                    config.map_file = path;
+                }
+                "--sensor" => config.sensor = value(&arg, args.next())?,
+                "--memory" => config.memory = true,
+                "--comms" => {
+                    config.comms.get_or_insert_default();
+                }
+                "--comms-range" => {
+                    config.comms.get_or_insert_default().range = Some(value(&arg, args.next())?);
+                }
+                "--comms-latency" => {
+                    config.comms.get_or_insert_default().latency = value(&arg, args.next())?;
+                }
+                "--comms-bandwidth" => {
+                    config.comms.get_or_insert_default().bandwidth =
+                        Some(value(&arg, args.next())?);
+                }
+                "--comms-loss" => {
+                    config.comms.get_or_insert_default().loss = value(&arg, args.next())?;
+                }
+                "--mode" => config.game.mode = value(&arg, args.next())?,
+                "--time-limit" => config.game.time_limit = Some(value(&arg, args.next())?),
+                "--target-score" => config.episode.target_score = Some(value(&arg, args.next())?),
+                "--no-respawn" => config.respawn = false,
+                "--stuck-after" => {
+                    let ticks: u64 = value(&arg, args.next())?;
+                    config.episode.stuck_after = (ticks > 0).then_some(ticks);
+                }
+                "--auto-reset" => config.episode.auto_reset = true,
+                "--seed" => config.seed = Some(value(&arg, args.next())?),
+                "--train" => config.train = Some(value(&arg, args.next())?),
+                "--evaluate" => config.evaluate = Some(value(&arg, args.next())?),
+                "--q-table" => config.q_table = value(&arg, args.next())?,
+                "--q-agent" => config.q_agent = true,
+                "--play" => config.play = true,
+                "--external" => config.external.push(value(&arg, args.next())?),
+                "--external-timeout" => config.external_timeout = value(&arg, args.next())?,
+                "--serve" => config.serve = Some(value(&arg, args.next())?),
+                "--headless" => config.headless = Some(value(&arg, args.next())?),
+                "--tui" => config.tui = true,
+                "--heatmap" => config.heatmap = Some(value(&arg, args.next())?),
+                "--frames" => config.frames = Some(value(&arg, args.next())?),
+                "--gif" => config.gif = Some(value(&arg, args.next())?),
+                "--record-every" => config.record_every = value(&arg, args.next())?,
+                "-h" | "--help" => return Err(USAGE.to_string()),
+                _ => return Err(format!("unknown option '{arg}'\n\n{USAGE}")),
+            }
+        }
+        if config.door_period == 0 || config.blink_period == 0 || config.record_every == 0 {
+            return Err("periods must be at least 1 tick".to_string());
+        }
+        if config.comms.is_some_and(|c| c.loss > 100) {
+            return Err("message loss is a percentage, at most 100".to_string());
+        }
+        if let Some(ref map) = config.map {
+            if !map.is_connected(config.topology) {
+                return Err("the map walls off part of the grid".to_string());
+            }
+            config.agents = u8::try_from(map.count(Item::Agent))
+                .map_err(|_| "too many agents on the map".to_string())?;
+        }
+        if config.play && (config.headless.is_some() || config.tui) {
+            return Err("you can't play without a window".to_string());
+        }
+        if config.play && config.q_agent {
+            return Err("the Q-learning agent and you can't both play agent 1".to_string());
+        }
+        if usize::from(config.first_external()) - 1 + config.external.len()
+            > usize::from(config.agents)
+        {
+            return Err("more controlled agents than agents".to_string());
+        }
+        Ok(config)
+    }
+
+    /// Id of the agent played by the first external process, after the one
+    /// played by the Q-learning agent or with the keyboard
+    pub fn first_external(&self) -> u8 {
+        1 + u8::from(self.q_agent || self.play)
+    }
+
+    /// Ids of the agents not controlled by the Q-learning agent, the keyboard
+    /// or external processes
+    pub fn free_agents(&self) -> Vec<u8> {
+        (self.first_external() + self.external.len() as u8..=self.agents).collect()
+    }
+}
+
+/// Parse the value following `option`
+fn value<T: FromStr>(option: &str, value: Option<String>) -> Result<T, String> {
+    let value = value.ok_or_else(|| format!("missing value for '{option}'"))?;
+    value
+        .parse()
+        .map_err(|_| format!("invalid value '{value}' for '{option}'"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::GameMode;
+
+    fn parse(args: &[&str]) -> Result<Config, String> {
+        Config::parse(args.iter().map(|s| s.to_string()))
+    }
+
+    #[test]
+    fn test_defaults() {
+        assert_eq!(parse(&[]).unwrap(), Config::default());
+    }
+
+    #[test]
+    fn test_parse_obstacle_options() {
+        let config = parse(&[
+            "--wandering-obstacles",
+            "3",
+            "--doors",
+            "2",
+            "--door-period",
+            "5",
+        ])
+        .unwrap();
+        assert_eq!(config.wandering_obstacles, 3);
+        assert_eq!(config.doors, 2);
+        assert_eq!(config.door_period, 5);
+        assert_eq!(config.blinking_obstacles, 0);
+    }
+
+    #[test]
+    fn test_parse_comms_options() {
+        assert_eq!(parse(&[]).unwrap().comms, None);
+        assert_eq!(
+            parse(&["--comms"]).unwrap().comms,
+            Some(CommsConfig::default())
+        );
+        let config = parse(&["--comms-range", "8", "--comms-loss", "25"]).unwrap();
+        assert_eq!(
+            config.comms,
+            Some(CommsConfig {
+                range: Some(8),
+                loss: 25,
+                ..CommsConfig::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_game_options() {
+        let config = parse(&["--mode", "teams:3", "--time-limit", "500"]).unwrap();
+        assert_eq!(
+            config.game,
+            Game {
+                mode: GameMode::Teams(3),
+                time_limit: Some(500),
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_episode_options() {
+        let config =
+            parse(&["--no-respawn", "--stuck-after", "0", "--target-score", "50"]).unwrap();
+        assert!(!config.respawn);
+        assert_eq!(
+            config.episode,
+            EpisodeConfig {
+                target_score: Some(50),
+                stuck_after: None,
+                auto_reset: false,
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_external_agents() {
+        let config = parse(&[
+            "--external",
+            "python3 agent.py",
+            "--external",
+            "./agent",
+            "--external-timeout",
+            "50",
+        ])
+        .unwrap();
+        assert_eq!(config.external, ["python3 agent.py", "./agent"]);
+        assert_eq!(config.external_timeout, 50);
+        assert!(parse(&["--agents", "1", "--q-agent", "--external", "./agent"]).is_err());
+        let config = parse(&["--agents", "4", "--q-agent", "--external", "./agent"]).unwrap();
+        assert_eq!(config.free_agents(), [3, 4]);
+        let config = parse(&["--agents", "3", "--play", "--external", "./agent"]).unwrap();
+        assert_eq!(config.first_external(), 2);
+        assert_eq!(config.free_agents(), [3]);
+        assert!(parse(&["--play", "--q-agent"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_map() {
+        let mut map = Map::new();
+        map.set(crate::location::Location::new(3, 3), Item::Agent);
+        map.set(crate::location::Location::new(4, 3), Item::Agent);
+        let path = std::env::temp_dir().join(format!("tileworld-map-{}.txt", std::process::id()));
+        map.save(&path).unwrap();
+        let config = parse(&["--map", path.to_str().unwrap()]);
+        std::fs::remove_file(&path).unwrap();
+        let config = config.unwrap();
+        assert_eq!(config.map, Some(map.clone()));
+        assert_eq!(config.map_file, path);
+        assert_eq!(config.agents, 2);
+        assert!(parse(&["--map", "/nonexistent/map.txt"]).is_err());
+
+        map.set(crate::location::Location::new(1, 0), Item::Obstacle);
+        map.set(crate::location::Location::new(0, 1), Item::Obstacle);
+        map.save(&path).unwrap();
+        let error = parse(&["--map", path.to_str().unwrap()]);
+        let torus = parse(&["--map", path.to_str().unwrap(), "--topology", "torus"]);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(error.unwrap_err(), "the map walls off part of the grid");
+        assert!(torus.is_ok());
+    }
+
+    #[test]
+    fn test_parse_headless_heatmap() {
+        let config = parse(&["--headless", "500", "--heatmap", "heat.png"]).unwrap();
+        assert_eq!(config.headless, Some(500));
+        assert_eq!(config.heatmap, Some(PathBuf::from("heat.png")));
+        assert!(parse(&["--headless", "500", "--play"]).is_err());
+        assert!(parse(&["--tui"]).unwrap().tui);
+        assert!(parse(&["--tui", "--play"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_recording() {
+        let config = parse(&[
+            "--gif",
+            "run.gif",
+            "--frames",
+            "frames",
+            "--record-every",
+            "5",
+        ])
+        .unwrap();
+        assert_eq!(config.gif, Some(PathBuf::from("run.gif")));
+        assert_eq!(config.frames, Some(PathBuf::from("frames")));
+        assert_eq!(config.record_every, 5);
+        assert!(parse(&["--record-every", "0"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(parse(&["--agents"]).is_err());
+        assert!(parse(&["--agents", "many"]).is_err());
+        assert!(parse(&["--bogus"]).is_err());
+        assert!(parse(&["--door-period", "0"]).is_err());
+        assert!(parse(&["--movement", "hex"]).is_err());
+        assert!(parse(&["--sensor", "-1"]).is_err());
+        assert!(parse(&["--comms-loss", "120"]).is_err());
+        assert!(parse(&["--mode", "teams:0"]).is_err());
+    }
+}
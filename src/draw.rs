@@ -1,44 +1,81 @@
 use crate::{
     COLS, MAG, ROWS,
-    grid::Grid,
-    location::Location,
-    objects::{AgentInfo, GO},
+    belief::Seen,
+    clock::Clock,
+    editor::Editor,
+    env::Env,
+    game::{GameMode, team_scores},
+    heatmap::{self, Layer},
+    history,
+    inspector::Inspector,
+    location::{Location, Topology},
+    objects::{AgentInfo, AgentState, GO, ObstacleKind},
+    sensor::Sensor,
+    view::{SIDEBAR, View},
 };
 use graphics::{
-    CharacterCache, CircleArc, Context, Ellipse, Graphics, Rectangle, Text,
+    CharacterCache, CircleArc, Context, Ellipse, Graphics, Line, Rectangle, Text,
     color::{BLACK, BLUE, GREEN, RED},
     types::Color,
 };
 use std::f64::consts::PI;
 
+/// Height of the score chart in the sidebar
+const CHART_HEIGHT: f64 = 60.0;
+
 pub fn draw<G: Graphics, C>(
-    grid: &Grid,
-    agents: &[AgentInfo],
+    env: &Env,
+    clock: &Clock,
+    view: &View,
     glyphs: &mut C,
-    ctx: &Context,
+    window: &Context,
     graphics: &mut G,
 ) where
     C: CharacterCache<Texture = G::Texture>,
 {
+    let ctx = &view.grid(window);
+    let (grid, agents, game, episode) = (env.grid(), env.agent_info(), env.game(), env.episode());
     let width = f64::from(u16::saturating_mul(COLS, MAG as u16));
     let height = f64::from(u16::saturating_mul(ROWS, MAG as u16));
-    Rectangle::new_border(BLACK, 1.0).draw(
-        [0.0, 0.0, width, height],
-        &ctx.draw_state,
-        ctx.transform,
-        graphics,
-    );
+    match grid.topology() {
+        Topology::Bounded => Rectangle::new_border(BLACK, 1.0).draw(
+            [0.0, 0.0, width, height],
+            &ctx.draw_state,
+            ctx.transform,
+            graphics,
+        ),
+        Topology::Torus => draw_open_border(width, height, ctx, graphics),
+    }
     for r in 0..ROWS {
         for c in 0..COLS {
             let x = f64::from(u16::saturating_mul(c, MAG as u16));
             let y = f64::from(u16::saturating_mul(r, MAG as u16));
             let location = Location::new(c, r);
+            if let Some(color) = grid.terrain(location).color() {
+                Rectangle::new(color).draw(
+                    [x, y, MAG, MAG],
+                    &ctx.draw_state,
+                    ctx.transform,
+                    graphics,
+                );
+            }
             if !grid.is_free(location)
                 && let Some(go) = grid.object(location)
             {
                 match *go.borrow() {
                     GO::Agent(ref a) => {
                         let color = get_color(a.id - 1);
+                        if let Sensor::Radius { radius, .. } = grid.sensor() {
+                            // faint circle showing how far the agent can see
+                            let r = (f64::from(radius) + 0.5) * MAG;
+                            let [red, green, blue, _] = color;
+                            CircleArc::new([red, green, blue, 0.3], 0.5, 0.0, 2f64 * PI).draw(
+                                [x + MAG / 2.0 - r, y + MAG / 2.0 - r, 2.0 * r, 2.0 * r],
+                                &ctx.draw_state,
+                                ctx.transform,
+                                graphics,
+                            );
+                        }
                         Rectangle::new_border(color, 1.0).draw(
                             [x, y, MAG, MAG],
                             &ctx.draw_state,
@@ -95,8 +132,8 @@ pub fn draw<G: Graphics, C>(
                             )
                             .unwrap();
                     }
-                    GO::Obstacle(_) => {
-                        Rectangle::new(BLACK).draw(
+                    GO::Obstacle(ref o) => {
+                        Rectangle::new(obstacle_color(o.kind)).draw(
                             [x, y, MAG, MAG],
                             &ctx.draw_state,
                             ctx.transform,
@@ -107,9 +144,27 @@ pub fn draw<G: Graphics, C>(
             }
         }
     }
-    let x = COLS as f64 * MAG + 50_f64;
+    // open doors are not on the grid, draw their frame so they can be seen
+    for o in grid.dynamic_obstacles() {
+        if let GO::Obstacle(ref o) = *o.borrow()
+            && let ObstacleKind::Door { .. } = o.kind
+            && !o.is_present()
+        {
+            let x = f64::from(o.location.col) * MAG;
+            let y = f64::from(o.location.row) * MAG;
+            Rectangle::new_border(obstacle_color(o.kind), 1.0).draw(
+                [x + 1.0, y + 1.0, MAG - 2.0, MAG - 2.0],
+                &ctx.draw_state,
+                ctx.transform,
+                graphics,
+            );
+        }
+    }
+    let ctx = &view.sidebar(window);
+    let x = 50f64;
     let y = 50f64;
 
+    let teams = matches!(game.mode, GameMode::Teams(_));
     for agent in agents {
         let id = agent.id;
         let score = agent.score;
@@ -125,10 +180,561 @@ pub fn draw<G: Graphics, C>(
                 graphics,
             )
             .unwrap();
+        if teams {
+            Rectangle::new(team_color(agent.team)).draw(
+                [x - 15.0, y + id as f64 * MAG - 9.0, 9.0, 9.0],
+                &ctx.draw_state,
+                ctx.transform,
+                graphics,
+            );
+        }
+    }
+
+    // below the agents: team scores, message counts and how the episode is going
+    let mut line = agents.len() as f64 + 2.0;
+    if teams {
+        for (team, score) in team_scores(agents) {
+            let text = format!("Team {team}: {score}");
+            sidebar_text(
+                &text,
+                team_color(team),
+                y + line * MAG,
+                glyphs,
+                ctx,
+                graphics,
+            );
+            line += 1.0;
+        }
+        line += 1.0;
+    }
+    if let Some(bus) = grid.comms() {
+        let stats = bus.stats();
+        let text = format!(
+            "Messages: {} sent, {} received",
+            stats.sent, stats.delivered
+        );
+        sidebar_text(&text, BLACK, y + line * MAG, glyphs, ctx, graphics);
+        line += 1.0;
+    }
+    let text = format!("Episode {}, tick {}", episode.number, grid.tick());
+    sidebar_text(&text, BLACK, y + line * MAG, glyphs, ctx, graphics);
+    line += 1.0;
+    sidebar_text(
+        &clock.status(),
+        BLACK,
+        y + line * MAG,
+        glyphs,
+        ctx,
+        graphics,
+    );
+    line += 1.0;
+    if let Some(reason) = episode.ended() {
+        let text = format!("Ended: {reason}");
+        sidebar_text(&text, BLACK, y + line * MAG, glyphs, ctx, graphics);
+        sidebar_text(
+            &game.result(agents),
+            BLACK,
+            y + (line + 1.0) * MAG,
+            glyphs,
+            ctx,
+            graphics,
+        );
+        line += 2.0;
+    } else if let Some(limit) = game.time_limit {
+        let text = format!("Ticks left: {}", limit.saturating_sub(grid.tick()));
+        sidebar_text(&text, BLACK, y + line * MAG, glyphs, ctx, graphics);
+        line += 1.0;
+    }
+    draw_chart(env, y + line * MAG, glyphs, ctx, graphics);
+}
+
+/// Each agent's score over the last ticks in its color, with the team totals
+/// (or the total of all agents), and how fast points come in overall
+fn draw_chart<G: Graphics, C>(env: &Env, top: f64, glyphs: &mut C, ctx: &Context, graphics: &mut G)
+where
+    C: CharacterCache<Texture = G::Texture>,
+{
+    let (history, agents) = (env.history(), env.agent_info());
+    let (x, width) = (50.0, SIDEBAR - 60.0);
+    let mut series: Vec<(Color, Vec<u32>)> = agents
+        .iter()
+        .enumerate()
+        .map(|(i, agent)| (get_color(agent.id - 1), history.total(|j| j == i)))
+        .collect();
+    if matches!(env.game().mode, GameMode::Teams(_)) {
+        for (team, _) in team_scores(agents) {
+            series.push((
+                team_color(team),
+                history.total(|i| agents.get(i).is_some_and(|a| a.team == team)),
+            ));
+        }
+    } else if agents.len() > 1 {
+        series.push((BLACK, history.total(|_| true)));
+    }
+    let max = series
+        .iter()
+        .flat_map(|(_, scores)| scores)
+        .copied()
+        .max()
+        .unwrap_or_default()
+        .max(1);
+    Rectangle::new_border([0.7, 0.7, 0.7, 1.0], 0.5).draw(
+        [x, top, width, CHART_HEIGHT],
+        &ctx.draw_state,
+        ctx.transform,
+        graphics,
+    );
+    let step = width / (history::TICKS - 1) as f64;
+    for (color, scores) in &series {
+        let point = |i: usize| {
+            [
+                x + i as f64 * step,
+                top + CHART_HEIGHT * (1.0 - f64::from(scores[i]) / f64::from(max)),
+            ]
+        };
+        let line = Line::new(*color, 0.6);
+        for i in 1..scores.len() {
+            let ([x1, y1], [x2, y2]) = (point(i - 1), point(i));
+            line.draw([x1, y1, x2, y2], &ctx.draw_state, ctx.transform, graphics);
+        }
+    }
+    // the scale, left of the chart
+    for (text, y) in [
+        (max.to_string(), top + 8.0),
+        ("0".to_string(), top + CHART_HEIGHT),
+    ] {
+        Text::new_color(BLACK, 10)
+            .draw_pos(
+                &text,
+                [25.0, y],
+                glyphs,
+                &ctx.draw_state,
+                ctx.transform,
+                graphics,
+            )
+            .unwrap();
+    }
+    let text = format!("{:.1} points/100 ticks", history.throughput());
+    sidebar_text(
+        &text,
+        BLACK,
+        top + CHART_HEIGHT + 15.0,
+        glyphs,
+        ctx,
+        graphics,
+    );
+}
+
+/// A line of small text in the sidebar
+fn sidebar_text<G: Graphics, C>(
+    text: &str,
+    color: Color,
+    y: f64,
+    glyphs: &mut C,
+    ctx: &Context,
+    graphics: &mut G,
+) where
+    C: CharacterCache<Texture = G::Texture>,
+{
+    Text::new_color(color, 10)
+        .draw_pos(
+            text,
+            [50.0, y],
+            glyphs,
+            &ctx.draw_state,
+            ctx.transform,
+            graphics,
+        )
+        .unwrap();
+}
+
+/// Overlay what one agent believes on top of the real grid: cells it never
+/// saw are greyed out, older memories fade to grey, and the obstacles, tiles
+/// and holes it remembers are marked in its color
+pub fn draw_belief<G: Graphics, C>(
+    agent: &AgentState,
+    tick: u64,
+    view: &View,
+    glyphs: &mut C,
+    window: &Context,
+    graphics: &mut G,
+) where
+    C: CharacterCache<Texture = G::Texture>,
+{
+    let ctx = &view.grid(window);
+    let color = get_color(agent.id - 1);
+    for r in 0..ROWS {
+        for c in 0..COLS {
+            let x = f64::from(c) * MAG;
+            let y = f64::from(r) * MAG;
+            let Some((seen, seen_at)) = agent.belief.cell(Location::new(c, r)) else {
+                Rectangle::new([0.2, 0.2, 0.2, 0.5]).draw(
+                    [x, y, MAG, MAG],
+                    &ctx.draw_state,
+                    ctx.transform,
+                    graphics,
+                );
+                continue;
+            };
+            let age = tick.saturating_sub(seen_at) as f32;
+            if age > 0.0 {
+                Rectangle::new([0.5, 0.5, 0.5, (age / 200.0).min(0.4)]).draw(
+                    [x, y, MAG, MAG],
+                    &ctx.draw_state,
+                    ctx.transform,
+                    graphics,
+                );
+            }
+            match seen {
+                Seen::Obstacle => {
+                    let line = Line::new(color, 1.0);
+                    line.draw(
+                        [x, y, x + MAG, y + MAG],
+                        &ctx.draw_state,
+                        ctx.transform,
+                        graphics,
+                    );
+                    line.draw(
+                        [x + MAG, y, x, y + MAG],
+                        &ctx.draw_state,
+                        ctx.transform,
+                        graphics,
+                    );
+                }
+                Seen::Tile(score) => {
+                    CircleArc::new(color, 1.0, 0.0, 2f64 * PI).draw(
+                        [x + 2.0, y + 2.0, MAG - 4.0, MAG - 4.0],
+                        &ctx.draw_state,
+                        ctx.transform,
+                        graphics,
+                    );
+                    Text::new_color(color, 10)
+                        .draw_pos(
+                            &score.to_string(),
+                            [x + MAG / 3f64, y + MAG - 6.0],
+                            glyphs,
+                            &ctx.draw_state,
+                            ctx.transform,
+                            graphics,
+                        )
+                        .unwrap();
+                }
+                Seen::Hole => {
+                    Ellipse::new(color).draw_from_to(
+                        [x + MAG / 4.0, y + MAG / 4.0],
+                        [x + 3.0 * MAG / 4.0, y + 3.0 * MAG / 4.0],
+                        &ctx.draw_state,
+                        ctx.transform,
+                        graphics,
+                    );
+                }
+                Seen::Free | Seen::Agent(_) => {}
+            }
+        }
+    }
+    let ctx = &view.sidebar(window);
+    let text = format!("Belief of Agent({})", agent.id);
+    Text::new_color(color, 12)
+        .draw_pos(
+            text.as_str(),
+            [50.0, 30.0],
+            glyphs,
+            &ctx.draw_state,
+            ctx.transform,
+            graphics,
+        )
+        .unwrap();
+}
+
+/// Mark the agent played with the keyboard, and remind the player of the keys
+pub fn draw_player<G: Graphics, C>(
+    agent: &AgentInfo,
+    view: &View,
+    glyphs: &mut C,
+    window: &Context,
+    graphics: &mut G,
+) where
+    C: CharacterCache<Texture = G::Texture>,
+{
+    let ctx = &view.grid(window);
+    let x = f64::from(agent.location.col) * MAG;
+    let y = f64::from(agent.location.row) * MAG;
+    Rectangle::new_border(get_color(agent.id - 1), 2.5).draw(
+        [x - 2.0, y - 2.0, MAG + 4.0, MAG + 4.0],
+        &ctx.draw_state,
+        ctx.transform,
+        graphics,
+    );
+    let (ctx, bottom) = (&view.sidebar(window), view.size()[1]);
+    let text = format!("You play Agent({})", agent.id);
+    sidebar_text(
+        &text,
+        get_color(agent.id - 1),
+        bottom - 3.0 * MAG,
+        glyphs,
+        ctx,
+        graphics,
+    );
+    sidebar_text(
+        "Arrows move, P picks up,",
+        BLACK,
+        bottom - 2.0 * MAG,
+        glyphs,
+        ctx,
+        graphics,
+    );
+    sidebar_text("D drops", BLACK, bottom - MAG, glyphs, ctx, graphics);
+}
+
+/// The map editor's tool and keys, shown while the simulation is paused
+pub fn draw_editor<G: Graphics, C>(
+    editor: &Editor,
+    view: &View,
+    glyphs: &mut C,
+    window: &Context,
+    graphics: &mut G,
+) where
+    C: CharacterCache<Texture = G::Texture>,
+{
+    let (ctx, bottom) = (&view.sidebar(window), view.size()[1]);
+    let status = editor.status();
+    let lines = [
+        status.as_str(),
+        "I, O, T (1-9), H, A choose,",
+        "click places, right click",
+        "clears, S saves the map",
+    ];
+    for (i, text) in lines.into_iter().enumerate() {
+        sidebar_text(
+            text,
+            BLACK,
+            bottom - (8.0 - i as f64) * MAG,
+            glyphs,
+            ctx,
+            graphics,
+        );
+    }
+}
+
+/// What is drawn over the grid to show how the agents plan, cycled with V
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Overlay {
+    #[default]
+    Off,
+    /// The path each agent follows and a line to its target
+    Paths,
+    /// Paths, plus the cells expanded by each agent's last path search
+    Search,
+}
+
+impl Overlay {
+    pub fn next(self) -> Self {
+        match self {
+            Overlay::Off => Overlay::Paths,
+            Overlay::Paths => Overlay::Search,
+            Overlay::Search => Overlay::Off,
+        }
+    }
+}
+
+/// Draw the planned paths and targets of the agents, in their colors
+pub fn draw_paths<G: Graphics>(
+    env: &Env,
+    overlay: Overlay,
+    view: &View,
+    window: &Context,
+    graphics: &mut G,
+) {
+    if overlay == Overlay::Off {
+        return;
+    }
+    let ctx = &view.grid(window);
+    let center = |l: Location| {
+        [
+            (f64::from(l.col) + 0.5) * MAG,
+            (f64::from(l.row) + 0.5) * MAG,
+        ]
+    };
+    for agent in env.agents() {
+        let GO::Agent(ref a) = *agent.borrow() else {
+            continue;
+        };
+        let [red, green, blue, _] = get_color(a.id - 1);
+        if overlay == Overlay::Search {
+            for &l in a.expanded() {
+                Rectangle::new([red, green, blue, 0.12]).draw(
+                    [f64::from(l.col) * MAG, f64::from(l.row) * MAG, MAG, MAG],
+                    &ctx.draw_state,
+                    ctx.transform,
+                    graphics,
+                );
+            }
+        }
+        if let Some(target) = a.target() {
+            let [x1, y1] = center(a.location);
+            let [x2, y2] = center(target);
+            Line::new([red, green, blue, 0.4], 0.5).draw(
+                [x1, y1, x2, y2],
+                &ctx.draw_state,
+                ctx.transform,
+                graphics,
+            );
+        }
+        let line = Line::new([red, green, blue, 1.0], 1.5);
+        let mut from = a.location;
+        for to in a.path(env.grid()) {
+            // on a torus the path may leave one edge and come back at the
+            // other, then half of the step is drawn at each edge
+            let step = |from: u16, to: u16| match i32::from(to) - i32::from(from) {
+                d if d.abs() > 1 => -f64::from(d.signum()) * MAG,
+                d => f64::from(d) * MAG,
+            };
+            let (dx, dy) = (step(from.col, to.col), step(from.row, to.row));
+            let ([x1, y1], [x2, y2]) = (center(from), center(to));
+            if (x1 + dx, y1 + dy) == (x2, y2) {
+                line.draw([x1, y1, x2, y2], &ctx.draw_state, ctx.transform, graphics);
+            } else {
+                let (hx, hy) = (dx / 2.0, dy / 2.0);
+                line.draw(
+                    [x1, y1, x1 + hx, y1 + hy],
+                    &ctx.draw_state,
+                    ctx.transform,
+                    graphics,
+                );
+                line.draw(
+                    [x2 - hx, y2 - hy, x2, y2],
+                    &ctx.draw_state,
+                    ctx.transform,
+                    graphics,
+                );
+            }
+            from = to;
+        }
+    }
+}
+
+/// Shade the cells by how busy they were over the run, cycled with M
+pub fn draw_heatmap<G: Graphics, C>(
+    env: &Env,
+    layer: Layer,
+    view: &View,
+    glyphs: &mut C,
+    window: &Context,
+    graphics: &mut G,
+) where
+    C: CharacterCache<Texture = G::Texture>,
+{
+    if layer == Layer::Off {
+        return;
+    }
+    let ctx = &view.grid(window);
+    let heatmap = env.heatmap();
+    for (l, heat) in heatmap.hot_cells(layer) {
+        let [red, green, blue] = heatmap::heat_color(heat);
+        Rectangle::new([red, green, blue, 0.6]).draw(
+            [f64::from(l.col) * MAG, f64::from(l.row) * MAG, MAG, MAG],
+            &ctx.draw_state,
+            ctx.transform,
+            graphics,
+        );
     }
+    let ctx = &view.sidebar(window);
+    let what = if layer == Layer::Visits {
+        "visits"
+    } else {
+        "blocked moves"
+    };
+    let text = format!("Heatmap: {what}, up to {}", heatmap.max(layer));
+    sidebar_text(
+        &text,
+        BLACK,
+        view.size()[1] - 11.0 * MAG,
+        glyphs,
+        ctx,
+        graphics,
+    );
 }
 
-fn get_color(num: u8) -> Color {
+/// Outline what the inspector has selected and describe it in the sidebar
+pub fn draw_inspector<G: Graphics, C>(
+    inspector: &Inspector,
+    env: &Env,
+    view: &View,
+    glyphs: &mut C,
+    window: &Context,
+    graphics: &mut G,
+) where
+    C: CharacterCache<Texture = G::Texture>,
+{
+    let Some(l) = inspector.location(env) else {
+        return;
+    };
+    let ctx = &view.grid(window);
+    let color = [1.0, 0.5, 0.0, 1.0];
+    Rectangle::new_border(color, 1.5).draw(
+        [
+            f64::from(l.col) * MAG - 3.0,
+            f64::from(l.row) * MAG - 3.0,
+            MAG + 6.0,
+            MAG + 6.0,
+        ],
+        &ctx.draw_state,
+        ctx.transform,
+        graphics,
+    );
+    let (ctx, top) = (&view.sidebar(window), view.size()[1] - 20.0 * MAG);
+    for (i, text) in inspector.lines(env).iter().enumerate() {
+        sidebar_text(
+            text,
+            BLACK,
+            top + i as f64 * MAG * 0.75,
+            glyphs,
+            ctx,
+            graphics,
+        );
+    }
+}
+
+/// A dashed border, showing that objects leaving one edge enter at the opposite one
+fn draw_open_border<G: Graphics>(width: f64, height: f64, ctx: &Context, graphics: &mut G) {
+    let line = Line::new([0.5, 0.5, 0.5, 1.], 0.5);
+    let dash = MAG / 2.0;
+    let mut x = 0.0;
+    while x < width {
+        for y in [0.0, height] {
+            line.draw(
+                [x, y, x + dash, y],
+                &ctx.draw_state,
+                ctx.transform,
+                graphics,
+            );
+        }
+        x += MAG;
+    }
+    let mut y = 0.0;
+    while y < height {
+        for x in [0.0, width] {
+            line.draw(
+                [x, y, x, y + dash],
+                &ctx.draw_state,
+                ctx.transform,
+                graphics,
+            );
+        }
+        y += MAG;
+    }
+}
+
+pub(crate) fn obstacle_color(kind: ObstacleKind) -> Color {
+    match kind {
+        ObstacleKind::Static => BLACK,
+        ObstacleKind::Wandering => [0.3, 0.3, 0.3, 1.],
+        ObstacleKind::Door { .. } => [0.55, 0.35, 0.15, 1.],
+        ObstacleKind::Blinking { .. } => [0.6, 0.6, 0.6, 1.],
+    }
+}
+
+pub(crate) fn get_color(num: u8) -> Color {
     match num {
         0 => BLUE,
         1 => RED,
@@ -139,3 +745,13 @@ fn get_color(num: u8) -> Color {
         _ => [0.5, 0.5, 0.5, 1.],
     }
 }
+
+pub(crate) fn team_color(team: u8) -> Color {
+    match team {
+        1 => [1., 0.5, 0., 1.],
+        2 => [0.4, 0.2, 0.8, 1.],
+        3 => [0., 0.7, 0.9, 1.],
+        4 => [0.6, 0.4, 0.2, 1.],
+        _ => [0.5, 0.5, 0.5, 1.],
+    }
+}
@@ -0,0 +1,175 @@
+use crate::{env::Env, location::Location, map::Item};
+use piston::{Key, MouseButton};
+
+/// What a left click does
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tool {
+    /// Leaves the grid alone, the click is for the inspector
+    Inspect,
+    Obstacle,
+    Tile,
+    Hole,
+    Agent,
+}
+
+/// Edits the grid with the mouse while the simulation is paused. O, T, H and A
+/// pick obstacles, tiles, holes or agents, the digits 1 to 9 pick tiles with
+/// that score, and I goes back to inspecting. A left click puts the item on a
+/// free cell, a right click clears the cell. Dragging with a button held does
+/// the same to every cell passed.
+#[derive(Debug)]
+pub struct Editor {
+    tool: Tool,
+    score: u32,
+    held: Option<MouseButton>,
+}
+
+impl Default for Editor {
+    fn default() -> Self {
+        Editor {
+            tool: Tool::Inspect,
+            score: 3,
+            held: None,
+        }
+    }
+}
+
+impl Editor {
+    pub fn new() -> Self {
+        Editor::default()
+    }
+
+    /// Returns whether the key is one of the editor's
+    pub fn press(&mut self, key: Key) -> bool {
+        self.tool = match key {
+            Key::I => Tool::Inspect,
+            Key::O => Tool::Obstacle,
+            Key::T => Tool::Tile,
+            Key::H => Tool::Hole,
+            Key::A => Tool::Agent,
+            _ => match digit(key) {
+                Some(score) => {
+                    self.score = score;
+                    Tool::Tile
+                }
+                None => return false,
+            },
+        };
+        true
+    }
+
+    /// Whether clicks are left to the inspector
+    pub fn inspecting(&self) -> bool {
+        self.tool == Tool::Inspect
+    }
+
+    /// What a left click puts on the grid, None when inspecting
+    pub fn item(&self) -> Option<Item> {
+        match self.tool {
+            Tool::Inspect => None,
+            Tool::Obstacle => Some(Item::Obstacle),
+            Tool::Tile => Some(Item::Tile(self.score)),
+            Tool::Hole => Some(Item::Hole),
+            Tool::Agent => Some(Item::Agent),
+        }
+    }
+
+    /// A mouse button went down over the cell at `l`
+    pub fn click(&mut self, env: &mut Env, l: Location, button: MouseButton) {
+        if !self.inspecting() {
+            self.held = Some(button);
+            self.drag(env, l);
+        }
+    }
+
+    /// The mouse moved onto the cell at `l`
+    pub fn drag(&mut self, env: &mut Env, l: Location) {
+        match self.held {
+            Some(MouseButton::Left) => {
+                if let Some(item) = self.item() {
+                    env.place(l, item);
+                }
+            }
+            Some(MouseButton::Right) => {
+                env.clear(l);
+            }
+            _ => {}
+        }
+    }
+
+    pub fn release(&mut self) {
+        self.held = None;
+    }
+
+    /// For the sidebar
+    pub fn status(&self) -> String {
+        match self.tool {
+            Tool::Inspect => "Inspecting".to_string(),
+            Tool::Obstacle => "Placing obstacles".to_string(),
+            Tool::Tile => format!("Placing tiles of {}", self.score),
+            Tool::Hole => "Placing holes".to_string(),
+            Tool::Agent => "Placing agents".to_string(),
+        }
+    }
+}
+
+fn digit(key: Key) -> Option<u32> {
+    let digits = [
+        Key::D1,
+        Key::D2,
+        Key::D3,
+        Key::D4,
+        Key::D5,
+        Key::D6,
+        Key::D7,
+        Key::D8,
+        Key::D9,
+    ];
+    (1..)
+        .zip(digits)
+        .find(|&(_, k)| k == key)
+        .map(|(score, _)| score)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    #[test]
+    fn test_tools() {
+        let mut editor = Editor::new();
+        assert!(editor.inspecting());
+        assert_eq!(editor.item(), None);
+        assert!(editor.press(Key::D7));
+        assert_eq!(editor.item(), Some(Item::Tile(7)));
+        editor.press(Key::H);
+        assert_eq!(editor.item(), Some(Item::Hole));
+        editor.press(Key::T);
+        assert_eq!(editor.item(), Some(Item::Tile(7)));
+        assert!(!editor.press(Key::B));
+    }
+
+    #[test]
+    fn test_drag_places_and_clears() {
+        let mut env = Env::new(Config {
+            agents: 0,
+            tiles: 0,
+            holes: 0,
+            obstacles: 0,
+            ..Config::default()
+        });
+        let mut editor = Editor::new();
+        editor.click(&mut env, Location::new(1, 1), MouseButton::Left);
+        assert!(env.grid().is_free(Location::new(1, 1)));
+        editor.press(Key::O);
+        editor.click(&mut env, Location::new(1, 1), MouseButton::Left);
+        editor.drag(&mut env, Location::new(2, 1));
+        editor.release();
+        editor.drag(&mut env, Location::new(3, 1));
+        assert!(env.grid().is_obstacle(Location::new(2, 1)));
+        assert!(!env.grid().is_obstacle(Location::new(3, 1)));
+        editor.click(&mut env, Location::new(1, 1), MouseButton::Right);
+        assert!(env.grid().is_free(Location::new(1, 1)));
+    }
+}
@@ -0,0 +1,608 @@
+use crate::{
+    COLS, ROWS,
+    config::Config,
+    episode::{Episode, Termination},
+    game::Game,
+    grid::Grid,
+    heatmap::Heatmap,
+    history::ScoreHistory,
+    location::Location,
+    map::{Item, Map},
+    objects::{
+        Action, AgentInfo, AgentState, AgentStats, GO, HoleState, Object, ObstacleKind,
+        ObstacleState, TileState,
+    },
+};
+use rand::Rng;
+use std::rc::Rc;
+
+/// Layers of an observation
+pub const OBSTACLES: usize = 0;
+/// Holds the score of each tile
+pub const TILES: usize = 1;
+pub const HOLES: usize = 2;
+/// Holds the id of each agent
+pub const AGENTS: usize = 3;
+/// Holds the score of the tile an agent carries
+pub const CARRYING: usize = 4;
+pub const CHANNELS: usize = 5;
+
+/// The whole grid as a stack of layers of ROWS x COLS cells
+#[derive(Debug, Clone, PartialEq)]
+pub struct Observation {
+    data: Vec<f32>,
+}
+
+impl Observation {
+    /// Channels, rows and columns
+    pub const SHAPE: [usize; 3] = [CHANNELS, ROWS as usize, COLS as usize];
+
+    fn empty() -> Self {
+        Observation {
+            data: vec![0.0; CHANNELS * usize::from(ROWS) * usize::from(COLS)],
+        }
+    }
+
+    fn index(channel: usize, l: Location) -> usize {
+        (channel * usize::from(ROWS) + usize::from(l.row)) * usize::from(COLS) + usize::from(l.col)
+    }
+
+    pub fn get(&self, channel: usize, l: Location) -> f32 {
+        self.data[Observation::index(channel, l)]
+    }
+
+    fn set(&mut self, channel: usize, l: Location, value: f32) {
+        self.data[Observation::index(channel, l)] = value;
+    }
+
+    /// All values, channel by channel and row by row
+    pub fn as_slice(&self) -> &[f32] {
+        &self.data
+    }
+}
+
+/// Extra information about a step
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StepInfo {
+    pub tick: u64,
+    /// Score of every agent, in order of id
+    pub scores: Vec<u32>,
+    pub termination: Option<Termination>,
+}
+
+/// The simulation behind a reset/step interface, for training agents against
+/// it. Agents without an action in a step plan their own moves.
+pub struct Env {
+    config: Config,
+    grid: Grid,
+    agents: Vec<Object>,
+    tiles: Vec<Object>,
+    holes: Vec<Object>,
+    episode: Episode,
+    agent_info: Vec<AgentInfo>,
+    history: ScoreHistory,
+    // kept across episodes
+    heatmap: Heatmap,
+}
+
+impl Env {
+    /// A new environment, already reset into its first episode using the
+    /// seed from the config, if any
+    pub fn new(config: Config) -> Self {
+        let (grid, agents, tiles, holes) = create_world(&config, config.seed);
+        let agent_info = collect_agent_info(&agents);
+        let mut history = ScoreHistory::new();
+        history.record(grid.tick(), &agent_info);
+        Env {
+            episode: Episode::new(1, config.episode),
+            config,
+            grid,
+            agents,
+            tiles,
+            holes,
+            agent_info,
+            history,
+            heatmap: Heatmap::new(),
+        }
+    }
+
+    /// Start the next episode on a fresh grid. Without a seed the random
+    /// numbers carry on from the previous episode, so a seeded environment
+    /// stays reproducible across resets. An episode that didn't get to a
+    /// single tick, like the one `new` starts, is replaced and keeps its number.
+    pub fn reset(&mut self, seed: Option<u64>) -> Observation {
+        let number = self.episode.number + u32::from(self.grid.tick() > 0);
+        let seed = seed.unwrap_or_else(|| self.grid.rng().random());
+        (self.grid, self.agents, self.tiles, self.holes) = create_world(&self.config, Some(seed));
+        self.agent_info = collect_agent_info(&self.agents);
+        self.episode = Episode::new(number, self.config.episode);
+        self.history.clear();
+        self.history.record(self.grid.tick(), &self.agent_info);
+        self.observe()
+    }
+
+    /// Advance one tick, with `actions` for the first agents. Rewards are the
+    /// points each agent scored in this tick. Once the episode is done, steps
+    /// change nothing until the next reset.
+    pub fn step(&mut self, actions: &[Action]) -> (Observation, Vec<f32>, bool, StepInfo) {
+        let actions: Vec<Option<Action>> = actions.iter().copied().map(Some).collect();
+        self.step_agents(&actions)
+    }
+
+    /// Like `step`, with an action or None for each agent in order of id.
+    /// Agents without an action plan their own moves.
+    pub fn step_agents(
+        &mut self,
+        actions: &[Option<Action>],
+    ) -> (Observation, Vec<f32>, bool, StepInfo) {
+        let before: Vec<u32> = self.agent_info.iter().map(|a| a.score).collect();
+        if self.episode.ended().is_none() {
+            let stats: Vec<AgentStats> = self.agents.iter().map(agent_stats).collect();
+            self.grid
+                .update_with(&self.agents, &self.tiles, &self.holes, actions);
+            for (agent, before) in self.agents.iter().zip(stats) {
+                let (l, after) = (*agent.borrow().location(), agent_stats(agent));
+                if after.steps > before.steps {
+                    self.heatmap.visit(l);
+                }
+                if after.blocked > before.blocked {
+                    self.heatmap.block(l);
+                }
+            }
+            self.agent_info = collect_agent_info(&self.agents);
+            self.history.record(self.grid.tick(), &self.agent_info);
+            let tiles_left = self.grid.remaining(&self.tiles).len();
+            let holes_left = self.grid.remaining(&self.holes).len();
+            self.episode.check(
+                &self.config.game,
+                self.grid.tick(),
+                &self.agent_info,
+                tiles_left,
+                holes_left,
+            );
+        }
+        let scores: Vec<u32> = self.agent_info.iter().map(|a| a.score).collect();
+        let rewards = scores
+            .iter()
+            .zip(before)
+            .map(|(&now, before)| (now - before) as f32)
+            .collect();
+        let info = StepInfo {
+            tick: self.grid.tick(),
+            scores,
+            termination: self.episode.ended(),
+        };
+        (self.observe(), rewards, info.termination.is_some(), info)
+    }
+
+    pub fn observe(&self) -> Observation {
+        let mut observation = Observation::empty();
+        for r in 0..ROWS {
+            for c in 0..COLS {
+                let l = Location::new(c, r);
+                if self.grid.is_obstacle(l) {
+                    observation.set(OBSTACLES, l, 1.0);
+                }
+            }
+        }
+        for t in self.tiles() {
+            let t = t.borrow();
+            observation.set(TILES, *t.location(), t.score() as f32);
+        }
+        for h in self.holes() {
+            observation.set(HOLES, *h.borrow().location(), 1.0);
+        }
+        for a in &self.agents {
+            if let GO::Agent(ref a) = *a.borrow() {
+                observation.set(AGENTS, a.location, f32::from(a.id));
+                if a.has_tile
+                    && let Some(ref tile) = a.tile
+                {
+                    observation.set(CARRYING, a.location, tile.borrow().score() as f32);
+                }
+            }
+        }
+        observation
+    }
+
+    /// What happened in the episode so far
+    pub fn summary(&self) -> String {
+        self.episode
+            .summary(&self.config.game, self.grid.tick(), &self.agent_info)
+    }
+
+    pub fn grid(&self) -> &Grid {
+        &self.grid
+    }
+
+    pub fn agents(&self) -> &[Object] {
+        &self.agents
+    }
+
+    /// The tiles still in play
+    pub fn tiles(&self) -> Vec<Object> {
+        self.grid.remaining(&self.tiles)
+    }
+
+    /// The holes still in play
+    pub fn holes(&self) -> Vec<Object> {
+        self.grid.remaining(&self.holes)
+    }
+
+    pub fn agent_info(&self) -> &[AgentInfo] {
+        &self.agent_info
+    }
+
+    /// Where agent `id` is among the agents, and so where its action goes
+    /// in a step. Ids have gaps once agents were taken off the grid.
+    pub fn agent_slot(&self, id: u8) -> Option<usize> {
+        self.agent_info.iter().position(|a| a.id == id)
+    }
+
+    pub fn episode(&self) -> &Episode {
+        &self.episode
+    }
+
+    pub fn game(&self) -> &Game {
+        &self.config.game
+    }
+
+    /// Where the agents went and got stuck, over all episodes so far
+    pub fn heatmap(&self) -> &Heatmap {
+        &self.heatmap
+    }
+
+    /// The scores over the last ticks of this episode
+    pub fn history(&self) -> &ScoreHistory {
+        &self.history
+    }
+
+    /// Put a new `item` on the free cell at `l`, for editing the grid. A new
+    /// agent gets the lowest id that's free. Returns false when the cell is
+    /// taken, an obstacle would wall off part of the grid, or there are as
+    /// many agents as there are ids.
+    pub fn place(&mut self, l: Location, item: Item) -> bool {
+        let placeable = match item {
+            Item::Obstacle => self.grid.keeps_connected(l),
+            Item::Agent => self.agents.len() < usize::from(u8::MAX),
+            _ => true,
+        };
+        if !self.grid.is_free(l) || !placeable {
+            return false;
+        }
+        place_item(
+            &mut self.grid,
+            l,
+            item,
+            &mut self.agents,
+            &mut self.tiles,
+            &mut self.holes,
+        );
+        self.config.game.assign_teams(&self.agents);
+        self.agent_info = collect_agent_info(&self.agents);
+        if item == Item::Agent {
+            self.restart_history();
+        }
+        true
+    }
+
+    /// Take whatever is on the cell at `l` off the grid, for editing it. The
+    /// other agents keep their ids, so whoever plays them keeps doing so.
+    /// Returns false when the cell was empty.
+    pub fn clear(&mut self, l: Location) -> bool {
+        let Some(o) = self.grid.clear(l) else {
+            return false;
+        };
+        self.tiles.retain(|t| !Rc::ptr_eq(t, &o));
+        self.holes.retain(|h| !Rc::ptr_eq(h, &o));
+        let agent = self.agents.iter().position(|a| Rc::ptr_eq(a, &o));
+        if let Some(i) = agent {
+            self.agents.remove(i);
+            // the agent may have been standing on a tile or hole
+            let under = self
+                .tiles
+                .iter()
+                .chain(&self.holes)
+                .find(|x| *x.borrow().location() == l);
+            if let Some(under) = under {
+                self.grid.insert_object(under.clone(), l);
+            }
+        }
+        self.agent_info = collect_agent_info(&self.agents);
+        if agent.is_some() {
+            self.restart_history();
+        }
+        true
+    }
+
+    /// Agents were added or removed, so the scores kept so far no longer
+    /// line up with the agents
+    fn restart_history(&mut self) {
+        self.history.clear();
+        self.history.record(self.grid.tick(), &self.agent_info);
+    }
+
+    /// The grid as a map: its static obstacles, tiles, holes and agents
+    pub fn map(&self) -> Map {
+        let mut map = Map::new();
+        for r in 0..ROWS {
+            for c in 0..COLS {
+                let l = Location::new(c, r);
+                if let Some(o) = self.grid.object(l)
+                    && let GO::Obstacle(ref o) = *o.borrow()
+                    && o.kind == ObstacleKind::Static
+                {
+                    map.set(l, Item::Obstacle);
+                }
+            }
+        }
+        for t in self.tiles() {
+            let t = t.borrow();
+            map.set(*t.location(), Item::Tile(t.score()));
+        }
+        for h in self.holes() {
+            map.set(*h.borrow().location(), Item::Hole);
+        }
+        for a in &self.agent_info {
+            map.set(a.location, Item::Agent);
+        }
+        map
+    }
+}
+
+/// A fresh grid set up as configured, with its agents, tiles and holes
+fn create_world(
+    config: &Config,
+    seed: Option<u64>,
+) -> (Grid, Vec<Object>, Vec<Object>, Vec<Object>) {
+    let mut g = seed.map_or_else(Grid::new, Grid::with_seed);
+    g.set_movement(config.movement);
+    g.set_topology(config.topology);
+    g.set_sensor(config.sensor);
+    g.set_memory(config.memory);
+    g.set_respawn(config.respawn);
+    if let Some(comms) = config.comms {
+        g.set_comms(comms);
+    }
+    g.generate_terrain(config.terrain_patches);
+    let (agents, tiles, holes) = match config.map {
+        Some(ref map) => {
+            let (mut agents, mut tiles, mut holes) = (vec![], vec![], vec![]);
+            for (l, item) in map.items() {
+                place_item(&mut g, l, item, &mut agents, &mut tiles, &mut holes);
+            }
+            (agents, tiles, holes)
+        }
+        None => {
+            g.create_layout(config.generator);
+            g.create_objects(config.agents, config.tiles, config.holes, config.obstacles)
+        }
+    };
+    g.create_dynamic_obstacles(ObstacleKind::Wandering, config.wandering_obstacles);
+    g.create_dynamic_obstacles(
+        ObstacleKind::Door {
+            period: config.door_period,
+            phase: 0,
+            open: false,
+        },
+        config.doors,
+    );
+    g.create_dynamic_obstacles(
+        ObstacleKind::Blinking {
+            period: config.blink_period,
+            phase: 0,
+            visible: true,
+        },
+        config.blinking_obstacles,
+    );
+    config.game.assign_teams(&agents);
+    (g, agents, tiles, holes)
+}
+
+/// Put a new `item` at `l`, adding it to the agents, tiles or holes
+fn place_item(
+    g: &mut Grid,
+    l: Location,
+    item: Item,
+    agents: &mut Vec<Object>,
+    tiles: &mut Vec<Object>,
+    holes: &mut Vec<Object>,
+) {
+    match item {
+        Item::Obstacle => {
+            g.place(GO::Obstacle(ObstacleState::new(l, ObstacleKind::Static)));
+        }
+        Item::Tile(score) => tiles.push(g.place(GO::Tile(TileState { location: l, score }))),
+        Item::Hole => holes.push(g.place(GO::Hole(HoleState { location: l }))),
+        Item::Agent => {
+            // the lowest id that's free, keeping the agents in order of id
+            let ids = collect_agent_info(agents).into_iter().map(|a| a.id);
+            let slot = (1..)
+                .zip(ids)
+                .take_while(|&(id, taken)| id == taken)
+                .count();
+            let id = slot as u8 + 1;
+            agents.insert(slot, g.place(GO::Agent(Box::new(AgentState::new(l, id)))));
+        }
+    }
+}
+
+fn collect_agent_info(agents: &[Object]) -> Vec<AgentInfo> {
+    agents
+        .iter()
+        .filter_map(|go| {
+            if let GO::Agent(ref a) = *go.borrow() {
+                Some(AgentInfo::from(a.as_ref()))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+fn agent_stats(agent: &Object) -> AgentStats {
+    match *agent.borrow() {
+        GO::Agent(ref a) => a.stats,
+        _ => AgentStats::default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{heatmap::Layer, location::Direction};
+
+    fn small_config() -> Config {
+        Config {
+            agents: 2,
+            tiles: 5,
+            holes: 5,
+            obstacles: 10,
+            wandering_obstacles: 2,
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn test_same_seed_same_episode() {
+        let mut a = Env::new(small_config());
+        let mut b = Env::new(small_config());
+        assert_eq!(a.reset(Some(7)), b.reset(Some(7)));
+        let actions = [Action::Move(Direction::Up), Action::Stay];
+        for _ in 0..50 {
+            let (obs_a, rewards_a, _, _) = a.step(&actions);
+            let (obs_b, rewards_b, _, _) = b.step(&actions);
+            assert_eq!(obs_a, obs_b);
+            assert_eq!(rewards_a, rewards_b);
+        }
+        // and the next episode too, without passing a seed again
+        assert_eq!(a.reset(None), b.reset(None));
+    }
+
+    #[test]
+    fn test_episode_numbers() {
+        let mut env = Env::new(small_config());
+        assert_eq!(env.episode().number, 1);
+        // the usual new, reset, step loop starts with the first episode
+        env.reset(Some(3));
+        assert_eq!(env.episode().number, 1);
+        env.step(&[Action::Stay]);
+        env.reset(None);
+        assert_eq!(env.episode().number, 2);
+    }
+
+    #[test]
+    fn test_observation_channels() {
+        let mut env = Env::new(small_config());
+        let obs = env.reset(Some(1));
+        let cells = usize::from(ROWS) * usize::from(COLS);
+        let layer = |channel: usize| {
+            obs.as_slice()[channel * cells..(channel + 1) * cells]
+                .iter()
+                .filter(|&&v| v > 0.0)
+                .count()
+        };
+        assert_eq!(
+            obs.as_slice().len(),
+            Observation::SHAPE.iter().product::<usize>()
+        );
+        assert_eq!(layer(OBSTACLES), 12);
+        assert_eq!(layer(TILES), 5);
+        assert_eq!(layer(HOLES), 5);
+        assert_eq!(layer(AGENTS), 2);
+        assert_eq!(layer(CARRYING), 0);
+        let agent = env.agent_info()[1].location;
+        assert_eq!(obs.get(AGENTS, agent), 2.0);
+    }
+
+    #[test]
+    fn test_edit_and_reload_the_map() {
+        let mut env = Env::new(small_config());
+        let free = env.grid().random_location().unwrap();
+        assert!(env.place(free, Item::Tile(4)));
+        assert!(!env.place(free, Item::Hole));
+        let first = env.agent_info()[0].location;
+        assert!(env.clear(first));
+        // the other agent keeps its id, and a new one takes the free id
+        assert_eq!(env.agent_info().len(), 1);
+        assert_eq!(env.agent_info()[0].id, 2);
+        assert_eq!((env.agent_slot(1), env.agent_slot(2)), (None, Some(0)));
+        assert!(env.place(first, Item::Agent));
+        assert_eq!(env.agent_info()[0].id, 1);
+        assert_eq!(env.agent_info()[0].location, first);
+        assert_eq!(env.agent_slot(2), Some(1));
+        assert!(!env.clear(env.grid().random_location().unwrap()));
+
+        let map = env.map();
+        assert_eq!(map.get(free), Some(Item::Tile(4)));
+        assert_eq!(map.count(Item::Agent), 2);
+        assert_eq!(map.count(Item::Hole), 5);
+        let config = Config {
+            map: Some(map.clone()),
+            ..small_config()
+        };
+        let reloaded = Env::new(config);
+        assert_eq!(reloaded.map(), map);
+        assert_eq!(reloaded.tiles().len(), 6);
+    }
+
+    #[test]
+    fn test_place_keeps_the_grid_connected() {
+        let mut map = Map::new();
+        map.set(Location::new(5, 3), Item::Agent);
+        let mut env = Env::new(Config {
+            agents: 1,
+            map: Some(map),
+            ..Config::default()
+        });
+        // the editor doesn't wall in the corner
+        assert!(env.place(Location::new(1, 0), Item::Obstacle));
+        assert!(!env.place(Location::new(0, 1), Item::Obstacle));
+        assert!(env.clear(Location::new(1, 0)));
+        assert!(env.place(Location::new(0, 1), Item::Obstacle));
+    }
+
+    #[test]
+    fn test_heatmap_counts_visits_and_blocked_moves() {
+        let mut map = Map::new();
+        map.set(Location::new(5, 3), Item::Agent);
+        map.set(Location::new(20, 20), Item::Tile(1));
+        map.set(Location::new(30, 30), Item::Hole);
+        let mut env = Env::new(Config {
+            agents: 1,
+            map: Some(map),
+            ..Config::default()
+        });
+        for _ in 0..5 {
+            env.step(&[Action::Move(Direction::Up)]);
+        }
+        let heatmap = env.heatmap();
+        assert_eq!(heatmap.get(Layer::Visits, Location::new(5, 1)), 1);
+        assert_eq!(heatmap.get(Layer::Visits, Location::new(5, 3)), 0);
+        assert_eq!(heatmap.max(Layer::Visits), 1);
+        // the top row is as far as it goes
+        assert_eq!(heatmap.get(Layer::Blocked, Location::new(5, 0)), 2);
+        assert_eq!(env.history().len(), 6);
+        // the counts carry over into the next episode, the scores start over
+        env.reset(None);
+        assert_eq!(env.heatmap().get(Layer::Blocked, Location::new(5, 0)), 2);
+        assert_eq!(env.history().len(), 1);
+    }
+
+    #[test]
+    fn test_rewards_add_up_to_scores() {
+        let mut env = Env::new(small_config());
+        env.reset(Some(3));
+        let mut total = [0.0; 2];
+        for _ in 0..300 {
+            let (_, rewards, done, info) = env.step(&[]);
+            total[0] += rewards[0];
+            total[1] += rewards[1];
+            assert_eq!(total, [info.scores[0] as f32, info.scores[1] as f32]);
+            if done {
+                break;
+            }
+        }
+        assert!(total[0] + total[1] > 0.0);
+    }
+}
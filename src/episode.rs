@@ -0,0 +1,208 @@
+use crate::{
+    game::{Game, team_scores},
+    location::Location,
+    objects::AgentInfo,
+};
+use std::fmt;
+
+/// Why an episode ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Termination {
+    TimeLimit,
+    TargetScore,
+    /// No more points can be scored: every tile or every hole has been used up
+    OutOfTiles,
+    /// No agent moved or scored for a while
+    Stuck,
+}
+
+impl fmt::Display for Termination {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Termination::TimeLimit => "time limit reached",
+            Termination::TargetScore => "target score reached",
+            Termination::OutOfTiles => "out of tiles",
+            Termination::Stuck => "agents are stuck",
+        })
+    }
+}
+
+/// When episodes end, besides the time limit of the game
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct EpisodeConfig {
+    /// Score a team (or a single agent in free-for-all) needs to end the episode
+    pub target_score: Option<u32>,
+    /// Ticks without any agent moving or scoring before they count as stuck,
+    /// None to wait forever
+    pub stuck_after: Option<u64>,
+    /// Start the next episode on a fresh grid when one ends
+    pub auto_reset: bool,
+}
+
+/// One run of the simulation, from a fresh grid until a termination condition
+#[derive(Debug)]
+pub struct Episode {
+    pub number: u32,
+    config: EpisodeConfig,
+    // tick of the last move or point scored, and where the agents were and
+    // what they had scored at that time
+    last_progress: u64,
+    last_agents: Vec<(Location, u32)>,
+    ended: Option<Termination>,
+}
+
+impl Episode {
+    pub fn new(number: u32, config: EpisodeConfig) -> Self {
+        Episode {
+            number,
+            config,
+            last_progress: 0,
+            last_agents: vec![],
+            ended: None,
+        }
+    }
+
+    pub fn ended(&self) -> Option<Termination> {
+        self.ended
+    }
+
+    /// Check the termination conditions after `tick`, with the given number of
+    /// tiles and holes still in play. Once ended, the episode stays ended.
+    pub fn check(
+        &mut self,
+        game: &Game,
+        tick: u64,
+        agents: &[AgentInfo],
+        tiles_left: usize,
+        holes_left: usize,
+    ) -> Option<Termination> {
+        if self.ended.is_some() {
+            return self.ended;
+        }
+        let now: Vec<(Location, u32)> = agents.iter().map(|a| (a.location, a.score)).collect();
+        if now != self.last_agents {
+            self.last_agents = now;
+            self.last_progress = tick;
+        }
+        let best = team_scores(agents).iter().map(|&(_, score)| score).max();
+        let carrying = agents.iter().any(|a| a.has_tile);
+        self.ended = if game.is_over(tick) {
+            Some(Termination::TimeLimit)
+        } else if self
+            .config
+            .target_score
+            .is_some_and(|target| best >= Some(target))
+        {
+            Some(Termination::TargetScore)
+        } else if holes_left == 0 || (tiles_left == 0 && !carrying) {
+            Some(Termination::OutOfTiles)
+        } else if self
+            .config
+            .stuck_after
+            .is_some_and(|t| tick - self.last_progress >= t)
+        {
+            Some(Termination::Stuck)
+        } else {
+            None
+        };
+        self.ended
+    }
+
+    /// What happened in the episode, for printing once it has ended
+    pub fn summary(&self, game: &Game, tick: u64, agents: &[AgentInfo]) -> String {
+        let reason = self
+            .ended
+            .map_or("still running".to_string(), |t| t.to_string());
+        let mut summary = format!("Episode {}: {reason} after {tick} ticks\n", self.number);
+        for agent in agents {
+            summary += &format!(
+                "  Agent({}) of team {}: {}\n",
+                agent.id, agent.team, agent.score
+            );
+        }
+        summary += &format!("  {}", game.result(agents));
+        summary
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn agent(col: u16, score: u32, has_tile: bool) -> AgentInfo {
+        AgentInfo {
+            id: 1,
+            team: 1,
+            score,
+            location: Location::new(col, 0),
+            has_tile,
+        }
+    }
+
+    #[test]
+    fn test_time_limit() {
+        let game = Game {
+            time_limit: Some(10),
+            ..Game::default()
+        };
+        let mut episode = Episode::new(1, EpisodeConfig::default());
+        assert_eq!(episode.check(&game, 9, &[agent(0, 0, false)], 5, 5), None);
+        assert_eq!(
+            episode.check(&game, 10, &[agent(1, 0, false)], 5, 5),
+            Some(Termination::TimeLimit)
+        );
+        assert_eq!(episode.ended(), Some(Termination::TimeLimit));
+    }
+
+    #[test]
+    fn test_target_score() {
+        let config = EpisodeConfig {
+            target_score: Some(10),
+            ..EpisodeConfig::default()
+        };
+        let mut episode = Episode::new(1, config);
+        let game = Game::default();
+        assert_eq!(episode.check(&game, 1, &[agent(0, 9, false)], 5, 5), None);
+        assert_eq!(
+            episode.check(&game, 2, &[agent(0, 10, false)], 5, 5),
+            Some(Termination::TargetScore)
+        );
+    }
+
+    #[test]
+    fn test_out_of_tiles_once_the_last_one_is_dropped() {
+        let mut episode = Episode::new(1, EpisodeConfig::default());
+        let game = Game::default();
+        assert_eq!(episode.check(&game, 1, &[agent(0, 0, true)], 0, 5), None);
+        assert_eq!(
+            episode.check(&game, 2, &[agent(1, 3, false)], 0, 4),
+            Some(Termination::OutOfTiles)
+        );
+    }
+
+    #[test]
+    fn test_stuck() {
+        let config = EpisodeConfig {
+            stuck_after: Some(5),
+            ..EpisodeConfig::default()
+        };
+        let mut episode = Episode::new(1, config);
+        let game = Game::default();
+        for tick in 1..5 {
+            assert_eq!(
+                episode.check(&game, tick, &[agent(tick as u16, 0, false)], 5, 5),
+                None
+            );
+        }
+        for tick in 5..9 {
+            assert_eq!(
+                episode.check(&game, tick, &[agent(4, 0, false)], 5, 5),
+                None
+            );
+        }
+        assert_eq!(
+            episode.check(&game, 9, &[agent(4, 0, false)], 5, 5),
+            Some(Termination::Stuck)
+        );
+    }
+}
@@ -0,0 +1,374 @@
+use crate::{
+    env::Env,
+    objects::Action,
+    protocol::{Percept, parse_action},
+};
+use log::warn;
+use std::{
+    io::{self, BufRead, BufReader, Read, Write},
+    process::{Child, Command, Stdio},
+    sync::mpsc::{Receiver, RecvTimeoutError, TryRecvError, channel},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Failed ticks in a row (no answer in time, or not a valid action) before a
+/// connection is given up on and its agent goes back to the built-in planner
+pub const MAX_FAILURES: u32 = 5;
+
+/// A connection to whatever drives an agent, exchanging lines of JSON: the
+/// percept goes out every tick and an action comes back
+pub struct Connection {
+    label: String,
+    writer: Box<dyn Write + Send>,
+    // lines from the other end, read on a separate thread so a silent peer
+    // can't block the simulation
+    lines: Receiver<String>,
+    failures: u32,
+    alive: bool,
+}
+
+impl Connection {
+    /// `label` names the other end in log messages
+    pub fn new(
+        label: String,
+        reader: impl Read + Send + 'static,
+        writer: impl Write + Send + 'static,
+    ) -> Self {
+        let (sender, lines) = channel();
+        thread::spawn(move || {
+            for line in BufReader::new(reader).lines() {
+                let Ok(line) = line else { break };
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        Connection {
+            label,
+            writer: Box::new(writer),
+            lines,
+            failures: 0,
+            alive: true,
+        }
+    }
+
+    pub fn set_label(&mut self, label: String) {
+        self.label = label;
+    }
+
+    /// Whether the other end is still there and behaving
+    pub fn is_alive(&self) -> bool {
+        self.alive
+    }
+
+    fn give_up(&mut self, reason: &str) {
+        warn!("{}: {reason}", self.label);
+        self.alive = false;
+    }
+
+    /// Write one line, giving up on the connection when that fails
+    pub fn send(&mut self, line: &str) {
+        if !self.alive {
+            return;
+        }
+        match self
+            .writer
+            .write_all(line.as_bytes())
+            .and_then(|_| self.writer.flush())
+        {
+            Ok(()) => {}
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                self.give_up("not taking in what is sent");
+            }
+            Err(_) => self.give_up("connection closed"),
+        }
+    }
+
+    /// A line that came in already, if any
+    pub fn try_receive(&mut self) -> Option<String> {
+        match self.lines.try_recv() {
+            Ok(line) => Some(line),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => {
+                self.give_up("connection closed");
+                None
+            }
+        }
+    }
+
+    /// Send the percept for this tick, dropping answers that came in too late
+    /// for an earlier tick
+    pub fn request(&mut self, percept: &Percept) {
+        while let Some(line) = self.try_receive() {
+            warn!("{}: late answer '{line}' ignored", self.label);
+        }
+        self.send(&percept.to_line());
+    }
+
+    /// Wait until `deadline` for the answer. A late or invalid answer counts
+    /// as a failure and the agent waits this tick. None once the connection
+    /// is given up on.
+    pub fn action(&mut self, deadline: Instant) -> Option<Action> {
+        if !self.alive {
+            return None;
+        }
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        let action = match self.lines.recv_timeout(timeout) {
+            Ok(line) => parse_action(&line),
+            Err(RecvTimeoutError::Timeout) => Err("no answer in time".to_string()),
+            Err(RecvTimeoutError::Disconnected) => {
+                self.give_up("connection closed");
+                return None;
+            }
+        };
+        match action {
+            Ok(action) => {
+                self.failures = 0;
+                Some(action)
+            }
+            Err(e) => {
+                warn!("{}: {e}", self.label);
+                self.failures += 1;
+                if self.failures >= MAX_FAILURES {
+                    self.give_up("too many failures");
+                    return None;
+                }
+                Some(Action::Stay)
+            }
+        }
+    }
+}
+
+/// An agent driven by a child process. Every tick it gets its percept as a
+/// line of JSON on stdin and answers with an action on stdout.
+pub struct ExternalAgent {
+    id: u8,
+    child: Child,
+    connection: Connection,
+}
+
+impl ExternalAgent {
+    /// Start `program` to drive agent `id`
+    pub fn spawn(id: u8, program: &str, args: &[&str]) -> io::Result<Self> {
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let stdin = child.stdin.take().expect("stdin is piped");
+        let stdout = child.stdout.take().expect("stdout is piped");
+        Ok(ExternalAgent {
+            id,
+            child,
+            connection: Connection::new(format!("agent {id}"), stdout, stdin),
+        })
+    }
+
+    pub fn id(&self) -> u8 {
+        self.id
+    }
+
+    /// Whether the process is still driving its agent
+    pub fn is_alive(&self) -> bool {
+        self.connection.is_alive()
+    }
+}
+
+impl Drop for ExternalAgent {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// The agents driven by child processes, asked for their actions all at once
+pub struct ExternalAgents {
+    agents: Vec<ExternalAgent>,
+    timeout: Duration,
+}
+
+impl ExternalAgents {
+    pub fn new(agents: Vec<ExternalAgent>, timeout: Duration) -> Self {
+        ExternalAgents { agents, timeout }
+    }
+
+    /// Start one process per command line, split into words like a shell
+    /// would, driving the agents from `first_id` onwards
+    pub fn spawn(commands: &[String], first_id: u8, timeout: Duration) -> io::Result<Self> {
+        let mut agents = vec![];
+        for (id, command) in (first_id..).zip(commands) {
+            let invalid = |e: String| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("agent command '{command}': {e}"),
+                )
+            };
+            let words = shell_words::split(command).map_err(|e| invalid(e.to_string()))?;
+            let (program, args) = words
+                .split_first()
+                .ok_or_else(|| invalid("empty".to_string()))?;
+            let args: Vec<&str> = args.iter().map(String::as_str).collect();
+            agents.push(ExternalAgent::spawn(id, program, &args)?);
+        }
+        Ok(ExternalAgents::new(agents, timeout))
+    }
+
+    /// The action of every agent in `env` for this tick, None for agents that
+    /// aren't driven by a process (anymore)
+    pub fn actions(&mut self, env: &Env) -> Vec<Option<Action>> {
+        let mut actions = vec![None; env.agent_info().len()];
+        let alive: Vec<bool> = self.agents.iter().map(|a| a.is_alive()).collect();
+        for agent in self.agents.iter_mut().filter(|a| a.is_alive()) {
+            if let Some(info) = env.agent_info().iter().find(|a| a.id == agent.id) {
+                agent.connection.request(&Percept::new(env, info));
+            }
+        }
+        // all processes think at the same time, they share the deadline
+        let deadline = Instant::now() + self.timeout;
+        for agent in self.agents.iter_mut().filter(|a| a.is_alive()) {
+            if let Some(slot) = env.agent_slot(agent.id).and_then(|i| actions.get_mut(i)) {
+                *slot = agent.connection.action(deadline);
+            }
+        }
+        // whether sending the percept or waiting for the answer failed, a
+        // process given up on doesn't get to run on unnoticed
+        for (agent, was_alive) in self.agents.iter_mut().zip(alive) {
+            if was_alive && !agent.is_alive() {
+                warn!("agent {}: the built-in planner takes over", agent.id);
+                let _ = agent.child.kill();
+            }
+        }
+        actions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::Config, location::Direction};
+
+    const TIMEOUT: Duration = Duration::from_millis(200);
+
+    fn env() -> Env {
+        Env::new(Config {
+            agents: 2,
+            ..Config::default()
+        })
+    }
+
+    #[cfg(unix)]
+    fn shell(id: u8, script: &str) -> ExternalAgents {
+        ExternalAgents::new(
+            vec![ExternalAgent::spawn(id, "sh", &["-c", script]).unwrap()],
+            TIMEOUT,
+        )
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_process_drives_its_agent() {
+        let env = env();
+        let mut agents = shell(
+            2,
+            r#"while read l; do echo '{"action": "move", "direction": "up"}'; done"#,
+        );
+        for _ in 0..3 {
+            assert_eq!(
+                agents.actions(&env),
+                vec![None, Some(Action::Move(Direction::Up))]
+            );
+        }
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_misbehaving_process_is_given_up() {
+        let env = env();
+        let mut agents = shell(1, "while read l; do echo nonsense; done");
+        for _ in 1..MAX_FAILURES {
+            assert_eq!(agents.actions(&env), vec![Some(Action::Stay), None]);
+        }
+        assert_eq!(agents.actions(&env), vec![None, None]);
+        assert!(!agents.agents[0].is_alive());
+        // and the process is gone, not left waiting for percepts
+        let start = Instant::now();
+        while agents.agents[0].child.try_wait().unwrap().is_none() {
+            assert!(
+                start.elapsed() < Duration::from_secs(2),
+                "process still running"
+            );
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_slow_process_waits() {
+        let env = env();
+        let mut agents = shell(
+            1,
+            r#"while read l; do sleep 1; echo '{"action": "pick"}'; done"#,
+        );
+        let start = Instant::now();
+        assert_eq!(agents.actions(&env), vec![Some(Action::Stay), None]);
+        assert!(start.elapsed() < Duration::from_millis(900));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_process_that_stops_reading_is_killed() {
+        let env = env();
+        let mut agents = shell(1, "exec 0<&-; sleep 30");
+        // sending the percept fails once the process closed its input
+        let start = Instant::now();
+        while agents.agents[0].is_alive() {
+            assert!(
+                start.elapsed() < Duration::from_secs(5),
+                "never given up on"
+            );
+            agents.actions(&env);
+        }
+        let start = Instant::now();
+        while agents.agents[0].child.try_wait().unwrap().is_none() {
+            assert!(
+                start.elapsed() < Duration::from_secs(2),
+                "process still running"
+            );
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_exited_process() {
+        let env = env();
+        let mut agents = shell(1, "exit 0");
+        assert_eq!(agents.actions(&env), vec![None, None]);
+        assert!(!agents.agents[0].is_alive());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_command_is_split_like_a_shell() {
+        let env = env();
+        let command = r#"sh -c 'while read l; do echo "{\"action\": \"pick\"}"; done'"#;
+        let mut agents = ExternalAgents::spawn(&[command.to_string()], 1, TIMEOUT).unwrap();
+        assert_eq!(agents.actions(&env), vec![Some(Action::Pick), None]);
+    }
+
+    #[test]
+    fn test_invalid_command() {
+        for command in ["", "python3 'agent.py"] {
+            let error = ExternalAgents::spawn(&[command.to_string()], 1, TIMEOUT)
+                .err()
+                .unwrap();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        }
+    }
+}
@@ -0,0 +1,191 @@
+use crate::{
+    comms::Recipient,
+    objects::{AgentInfo, GO, Object},
+};
+use std::str::FromStr;
+
+/// How the agents are split up
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum GameMode {
+    /// Everybody is on the same team
+    #[default]
+    Cooperative,
+    /// Agents are dealt round robin over this many teams
+    Teams(u8),
+    /// Every agent is on its own
+    FreeForAll,
+}
+
+impl GameMode {
+    /// The team agent `id` plays for, teams are numbered from 1
+    pub fn team_for(self, id: u8) -> u8 {
+        match self {
+            GameMode::Cooperative => 1,
+            GameMode::Teams(teams) => (id - 1) % teams.max(1) + 1,
+            GameMode::FreeForAll => id,
+        }
+    }
+}
+
+impl FromStr for GameMode {
+    type Err = String;
+
+    /// "coop", "ffa", or "teams" optionally followed by the number of teams, like "teams:3"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            None if s == "coop" => Ok(GameMode::Cooperative),
+            None if s == "ffa" => Ok(GameMode::FreeForAll),
+            None if s == "teams" => Ok(GameMode::Teams(2)),
+            Some(("teams", n)) => match n.parse() {
+                Ok(n) if n > 0 => Ok(GameMode::Teams(n)),
+                _ => Err(format!("invalid number of teams '{n}'")),
+            },
+            _ => Err(format!("unknown game mode '{s}'")),
+        }
+    }
+}
+
+/// The rules of a game: who plays with whom, and for how long
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Game {
+    pub mode: GameMode,
+    /// Ticks until the game is over, None to play forever
+    pub time_limit: Option<u64>,
+}
+
+impl Game {
+    /// Put every agent on the team the game mode has it play for. Sightings
+    /// are kept within a team only when there are teams to play against.
+    pub fn assign_teams(&self, agents: &[Object]) {
+        for agent in agents {
+            if let GO::Agent(ref mut a) = *agent.borrow_mut() {
+                a.team = self.mode.team_for(a.id);
+                a.sightings_to = match self.mode {
+                    GameMode::Teams(_) => Recipient::Team(a.team),
+                    _ => Recipient::Broadcast,
+                };
+            }
+        }
+    }
+
+    pub fn is_over(&self, tick: u64) -> bool {
+        self.time_limit.is_some_and(|limit| tick >= limit)
+    }
+
+    /// One line announcing who won, for when the game is over
+    pub fn result(&self, agents: &[AgentInfo]) -> String {
+        let scores = team_scores(agents);
+        let best = scores.iter().map(|&(_, score)| score).max().unwrap_or(0);
+        let winners: Vec<String> = scores
+            .iter()
+            .filter(|&&(_, score)| score == best)
+            .map(|&(team, _)| match self.mode {
+                GameMode::FreeForAll => format!("Agent({team})"),
+                _ => format!("Team {team}"),
+            })
+            .collect();
+        match self.mode {
+            GameMode::Cooperative => format!("Game over: {best} points"),
+            _ if winners.len() > 1 => format!("Game over: {} tie with {best}", winners.join(", ")),
+            _ => format!("Game over: {} wins with {best}", winners.join("")),
+        }
+    }
+}
+
+/// Total score of each team, ordered by team
+pub fn team_scores(agents: &[AgentInfo]) -> Vec<(u8, u32)> {
+    let mut scores: Vec<(u8, u32)> = vec![];
+    for agent in agents {
+        match scores.iter_mut().find(|(team, _)| *team == agent.team) {
+            Some((_, score)) => *score += agent.score,
+            None => scores.push((agent.team, agent.score)),
+        }
+    }
+    scores.sort();
+    scores
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{grid::Grid, location::Location};
+
+    fn agent(id: u8, team: u8, score: u32) -> AgentInfo {
+        AgentInfo {
+            id,
+            team,
+            score,
+            location: Location::new(0, 0),
+            has_tile: false,
+        }
+    }
+
+    #[test]
+    fn test_team_for() {
+        assert_eq!(GameMode::Cooperative.team_for(5), 1);
+        assert_eq!(GameMode::FreeForAll.team_for(5), 5);
+        let teams: Vec<u8> = (1..=6).map(|id| GameMode::Teams(3).team_for(id)).collect();
+        assert_eq!(teams, vec![1, 2, 3, 1, 2, 3]);
+    }
+
+    #[test]
+    fn test_who_hears_sightings() {
+        let (agents, _, _) = Grid::new().create_objects(2, 0, 0, 0);
+        let sightings_to = |mode| {
+            Game {
+                mode,
+                time_limit: None,
+            }
+            .assign_teams(&agents);
+            agents
+                .iter()
+                .map(|a| match *a.borrow() {
+                    GO::Agent(ref a) => a.sightings_to,
+                    _ => unreachable!(),
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            sightings_to(GameMode::Cooperative),
+            vec![Recipient::Broadcast; 2]
+        );
+        assert_eq!(
+            sightings_to(GameMode::FreeForAll),
+            vec![Recipient::Broadcast; 2]
+        );
+        assert_eq!(
+            sightings_to(GameMode::Teams(2)),
+            vec![Recipient::Team(1), Recipient::Team(2)]
+        );
+    }
+
+    #[test]
+    fn test_team_scores() {
+        let agents = [agent(1, 1, 5), agent(2, 2, 3), agent(3, 1, 4)];
+        assert_eq!(team_scores(&agents), vec![(1, 9), (2, 3)]);
+    }
+
+    #[test]
+    fn test_result() {
+        let game = Game {
+            mode: GameMode::Teams(2),
+            time_limit: Some(100),
+        };
+        assert!(!game.is_over(99));
+        assert!(game.is_over(100));
+        let agents = [agent(1, 1, 5), agent(2, 2, 7)];
+        assert_eq!(game.result(&agents), "Game over: Team 2 wins with 7");
+        let agents = [agent(1, 1, 7), agent(2, 2, 7)];
+        assert_eq!(game.result(&agents), "Game over: Team 1, Team 2 tie with 7");
+    }
+
+    #[test]
+    fn test_parse_mode() {
+        assert_eq!("coop".parse(), Ok(GameMode::Cooperative));
+        assert_eq!("teams".parse(), Ok(GameMode::Teams(2)));
+        assert_eq!("teams:4".parse(), Ok(GameMode::Teams(4)));
+        assert_eq!("ffa".parse(), Ok(GameMode::FreeForAll));
+        assert!("teams:0".parse::<GameMode>().is_err());
+        assert!("solo".parse::<GameMode>().is_err());
+    }
+}
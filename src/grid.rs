@@ -1,19 +1,73 @@
 use crate::{
     COLS, ROWS,
-    location::Location,
-    objects::{AgentState, GO, HoleState, Object, TileState},
+    comms::{CommsConfig, MessageBus},
+    location::{DIRECTIONS, Direction, Location, Movement, Topology},
+    mapgen::Generator,
+    objects::{Action, AgentState, GO, HoleState, Object, ObstacleKind, ObstacleState, TileState},
+    sensor::Sensor,
+    terrain::{TERRAINS, Terrain},
 };
-use rand::Rng;
-use std::{cell::RefCell, collections::HashMap, rc::Rc};
+use log::warn;
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use std::{
+    cell::{RefCell, RefMut},
+    collections::{HashMap, HashSet, VecDeque},
+    rc::Rc,
+};
+
+/// Source of all randomness on a grid, so a seed reproduces a whole run
+#[derive(Debug)]
+struct GridRng(RefCell<StdRng>);
+
+impl Default for GridRng {
+    fn default() -> Self {
+        GridRng(RefCell::new(StdRng::from_os_rng()))
+    }
+}
 
 #[derive(Default, Debug)]
 pub struct Grid {
     objects: HashMap<Location, Object>,
+    // Obstacles that move, open or vanish; updated every tick before the agents
+    dynamic: Vec<Object>,
+    // Cells without an entry are road
+    terrain: HashMap<Location, Terrain>,
+    movement: Movement,
+    topology: Topology,
+    sensor: Sensor,
+    // Whether agents remember what they have seen
+    memory: bool,
+    // Channel between the agents, None when they can't communicate
+    comms: Option<MessageBus>,
+    // Whether used tiles and holes reappear elsewhere
+    respawn: bool,
+    // Tiles and holes taken out of the game when respawning is off
+    used_up: Vec<Object>,
+    rng: GridRng,
+    tick: u64,
 }
 
 impl Grid {
     pub fn new() -> Self {
-        Grid::default()
+        Grid {
+            respawn: true,
+            ..Grid::default()
+        }
+    }
+
+    /// A grid on which everything random happens the same way every time for
+    /// the same seed
+    pub fn with_seed(seed: u64) -> Self {
+        Grid {
+            rng: GridRng(RefCell::new(StdRng::seed_from_u64(seed))),
+            ..Grid::new()
+        }
+    }
+
+    /// The grid's random number generator. Don't hold on to it while calling
+    /// other methods of the grid, they may need it too.
+    pub fn rng(&self) -> RefMut<'_, StdRng> {
+        self.rng.0.borrow_mut()
     }
 
     pub fn object(&self, l: Location) -> Option<&Object> {
@@ -25,13 +79,194 @@ impl Grid {
         self.objects.insert(new, o);
     }
 
+    pub fn insert_object(&mut self, o: Object, l: Location) {
+        self.objects.insert(l, o);
+    }
+
+    pub fn remove_object(&mut self, l: Location) -> Option<Object> {
+        self.objects.remove(&l)
+    }
+
+    /// Put a new object on the grid, at its location
+    pub fn place(&mut self, go: GO) -> Object {
+        let l = *go.location();
+        let r = Rc::new(RefCell::new(go));
+        self.objects.insert(l, r.clone());
+        r
+    }
+
+    /// Take whatever is at `l` off the grid for good, so a dynamic obstacle
+    /// doesn't come back either
+    pub fn clear(&mut self, l: Location) -> Option<Object> {
+        let o = self.objects.remove(&l)?;
+        self.dynamic.retain(|d| !Rc::ptr_eq(d, &o));
+        Some(o)
+    }
+
     pub fn is_free(&self, location: Location) -> bool {
         !self.objects.contains_key(&location)
     }
 
+    pub fn is_obstacle(&self, location: Location) -> bool {
+        self.objects
+            .get(&location)
+            .is_some_and(|o| matches!(*o.borrow(), GO::Obstacle(_)))
+    }
+
+    pub fn movement(&self) -> Movement {
+        self.movement
+    }
+
+    pub fn set_movement(&mut self, movement: Movement) {
+        self.movement = movement;
+    }
+
+    pub fn topology(&self) -> Topology {
+        self.topology
+    }
+
+    pub fn set_topology(&mut self, topology: Topology) {
+        self.topology = topology;
+    }
+
+    /// What agents can perceive
+    pub fn sensor(&self) -> Sensor {
+        self.sensor
+    }
+
+    pub fn set_sensor(&mut self, sensor: Sensor) {
+        self.sensor = sensor;
+    }
+
+    pub fn memory(&self) -> bool {
+        self.memory
+    }
+
+    pub fn set_memory(&mut self, memory: bool) {
+        self.memory = memory;
+    }
+
+    pub fn comms(&self) -> Option<&MessageBus> {
+        self.comms.as_ref()
+    }
+
+    pub fn comms_mut(&mut self) -> Option<&mut MessageBus> {
+        self.comms.as_mut()
+    }
+
+    /// Let the agents talk to each other. They need a memory to make use of
+    /// what they hear, so this turns it on as well.
+    pub fn set_comms(&mut self, config: CommsConfig) {
+        let seed = self.rng().random();
+        self.comms = Some(MessageBus::new(config, seed));
+        self.memory = true;
+    }
+
+    pub fn respawn(&self) -> bool {
+        self.respawn
+    }
+
+    pub fn set_respawn(&mut self, respawn: bool) {
+        self.respawn = respawn;
+    }
+
+    /// Take a tile or hole out of the game for good. It is no longer on the
+    /// grid, the agent that used it is standing in its place.
+    pub fn use_up(&mut self, o: Object) {
+        self.used_up.push(o);
+    }
+
+    /// The tiles or holes in `collection` that are still in play
+    pub fn remaining(&self, collection: &[Object]) -> Vec<Object> {
+        collection
+            .iter()
+            .filter(|o| !self.used_up.iter().any(|u| Rc::ptr_eq(u, o)))
+            .cloned()
+            .collect()
+    }
+
+    /// The location one step from `from` in direction `d`, wrapping around the
+    /// edges on a torus
+    pub fn next_location(&self, from: Location, d: Direction) -> Location {
+        self.topology.next_location(from, d)
+    }
+
+    /// Whether a step from `from` in direction `d` stays on the grid and
+    /// follows the movement rules. Diagonal steps may not cut the corner of
+    /// an obstacle. Whether the destination is free is up to the caller.
+    pub fn can_move(&self, from: Location, d: Direction) -> bool {
+        if !self.movement.directions().contains(&d) || !self.topology.is_valid_move(from, d) {
+            return false;
+        }
+        match d.components() {
+            Some((vertical, horizontal)) => {
+                !self.is_obstacle(self.next_location(from, vertical))
+                    && !self.is_obstacle(self.next_location(from, horizontal))
+            }
+            None => true,
+        }
+    }
+
+    /// Cost of stepping from `from` in direction `d`, in tenths of a tick
+    pub fn step_cost(&self, from: Location, d: Direction) -> u32 {
+        self.movement.step_cost(d) * self.terrain(self.next_location(from, d)).cost()
+    }
+
+    /// Lower bound on the cost of travelling between two locations, in tenths of a tick
+    pub fn estimate(&self, from: Location, to: Location) -> u32 {
+        let (col_diff, row_diff) = self.topology.offset(from, to);
+        self.movement.estimate(col_diff, row_diff)
+    }
+
+    pub fn terrain(&self, location: Location) -> Terrain {
+        self.terrain.get(&location).copied().unwrap_or_default()
+    }
+
+    pub fn set_terrain(&mut self, location: Location, terrain: Terrain) {
+        if terrain == Terrain::Road {
+            self.terrain.remove(&location);
+        } else {
+            self.terrain.insert(location, terrain);
+        }
+    }
+
+    /// Scatter `patches` roughly round blobs of grass, mud and water
+    pub fn generate_terrain(&mut self, patches: u8) {
+        for _i in 1..=patches {
+            let (center, terrain, radius) = {
+                let mut rng = self.rng();
+                let center = Location::new(rng.random_range(0..COLS), rng.random_range(0..ROWS));
+                let terrain = TERRAINS[rng.random_range(1..TERRAINS.len())];
+                let radius: u16 = rng.random_range(2..6);
+                (center, terrain, radius)
+            };
+            for r in center.row.saturating_sub(radius)..=(center.row + radius).min(ROWS - 1) {
+                for c in center.col.saturating_sub(radius)..=(center.col + radius).min(COLS - 1) {
+                    let l = Location::new(c, r);
+                    // fray the edges so the blobs don't all look like diamonds
+                    if l.distance(center) + self.rng().random_range(0..2) <= radius {
+                        self.set_terrain(l, terrain);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Number of updates since the grid was created
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    /// Obstacles that change over time, including open doors and vanished
+    /// obstacles that are currently not on the grid
+    pub fn dynamic_obstacles(&self) -> &[Object] {
+        &self.dynamic
+    }
+
     #[cfg(test)]
     pub fn add_obstacle(&mut self, location: Location) {
-        let r = Rc::new(RefCell::new(GO::Obstacle(location)));
+        let obstacle = ObstacleState::new(location, ObstacleKind::Static);
+        let r = Rc::new(RefCell::new(GO::Obstacle(obstacle)));
         self.objects.insert(location, r);
     }
 
@@ -41,7 +276,7 @@ impl Grid {
         let total_cells = (COLS as usize) * (ROWS as usize);
         let max_attempts = total_cells * 2; // Give up after 2x grid size attempts
 
-        let mut rng = rand::rng();
+        let mut rng = self.rng();
         for _ in 0..max_attempts {
             let c: u16 = rng.random_range(0..COLS);
             let r: u16 = rng.random_range(0..ROWS);
@@ -53,10 +288,171 @@ impl Grid {
         None
     }
 
+    /// Whether there is an obstacle at `location` that never moves
+    fn is_wall(&self, location: Location) -> bool {
+        self.objects.get(&location).is_some_and(
+            |o| matches!(*o.borrow(), GO::Obstacle(ref o) if o.kind == ObstacleKind::Static),
+        )
+    }
+
+    /// All cells an agent could walk to from `start` if it only had to get
+    /// around static obstacles
+    pub fn reachable_from(&self, start: Location) -> HashSet<Location> {
+        let mut seen = HashSet::from([start]);
+        let mut queue = VecDeque::from([start]);
+        while let Some(current) = queue.pop_front() {
+            for &d in self.movement.directions() {
+                if self.can_move(current, d) {
+                    let next = self.next_location(current, d);
+                    if !self.is_wall(next) && seen.insert(next) {
+                        queue.push_back(next);
+                    }
+                }
+            }
+        }
+        seen
+    }
+
+    /// Whether every cell without a static obstacle can be reached from every other one
+    pub fn is_connected(&self) -> bool {
+        let open: Vec<Location> = (0..ROWS)
+            .flat_map(|r| (0..COLS).map(move |c| Location::new(c, r)))
+            .filter(|&l| !self.is_wall(l))
+            .collect();
+        match open.first() {
+            Some(&start) => self.reachable_from(start).len() == open.len(),
+            None => true,
+        }
+    }
+
+    /// Whether a static obstacle at `l` would keep every open cell reachable
+    /// from every other one that is reachable now
+    pub fn keeps_connected(&self, l: Location) -> bool {
+        !self.cut_cells().contains(&l)
+    }
+
+    /// The open cells that would cut off part of the grid if they were walled
+    /// in, the cut vertices of the cells without static obstacles. One
+    /// depth-first search finds all of them, instead of running `is_connected`
+    /// for every cell in question.
+    fn cut_cells(&self) -> HashSet<Location> {
+        // a diagonal step needs both cells beside it open, so it never
+        // connects cells that aren't connected through those already
+        let index = |l: Location| usize::from(l.row) * usize::from(COLS) + usize::from(l.col);
+        let cells = || (0..ROWS).flat_map(|r| (0..COLS).map(move |c| Location::new(c, r)));
+        let walls: Vec<bool> = cells().map(|l| self.is_wall(l)).collect();
+        let neighbours = |l: Location| {
+            DIRECTIONS
+                .into_iter()
+                .filter(move |&d| self.topology.is_valid_move(l, d))
+                .map(move |d| self.next_location(l, d))
+                .filter(|&n| !walls[index(n)])
+        };
+        let mut cut = HashSet::new();
+        // when each cell was first visited, and the earliest visited cell it
+        // reaches without going back through its parent
+        let (mut order, mut low) = (vec![None; walls.len()], vec![0; walls.len()]);
+        let mut visited = 0;
+        for root in cells() {
+            if walls[index(root)] || order[index(root)].is_some() {
+                continue;
+            }
+            order[index(root)] = Some(visited);
+            low[index(root)] = visited;
+            visited += 1;
+            let mut root_children = 0;
+            let mut stack = vec![(root, root, neighbours(root))];
+            while let Some((l, parent, next)) = stack.last_mut() {
+                let (l, parent) = (*l, *parent);
+                if let Some(n) = next.next() {
+                    if n == parent {
+                        continue;
+                    }
+                    match order[index(n)] {
+                        Some(seen) => low[index(l)] = low[index(l)].min(seen),
+                        None => {
+                            order[index(n)] = Some(visited);
+                            low[index(n)] = visited;
+                            visited += 1;
+                            stack.push((n, l, neighbours(n)));
+                        }
+                    }
+                    continue;
+                }
+                stack.pop();
+                if l == root {
+                    continue;
+                }
+                low[index(parent)] = low[index(parent)].min(low[index(l)]);
+                if parent == root {
+                    root_children += 1;
+                } else if Some(low[index(l)]) >= order[index(parent)] {
+                    cut.insert(parent);
+                }
+            }
+            if root_children > 1 {
+                cut.insert(root);
+            }
+        }
+        cut
+    }
+
+    /// A random free location where an obstacle doesn't cut off any part of
+    /// the grid, or None if there is none
+    pub fn random_connected_location(&self) -> Option<Location> {
+        let cut = self.cut_cells();
+        let candidates: Vec<Location> = (0..ROWS)
+            .flat_map(|r| (0..COLS).map(move |c| Location::new(c, r)))
+            .filter(|l| self.is_free(*l) && !cut.contains(l))
+            .collect();
+        if candidates.is_empty() {
+            return None;
+        }
+        let i = self.rng().random_range(0..candidates.len());
+        Some(candidates[i])
+    }
+
+    /// Place a static obstacle at a random free location where it does not cut
+    /// off any part of the grid. Returns false if no such location was found.
+    fn place_connected_obstacle(&mut self) -> bool {
+        let Some(l) = self.random_connected_location() else {
+            return false;
+        };
+        let obstacle = ObstacleState::new(l, ObstacleKind::Static);
+        self.objects
+            .insert(l, Rc::new(RefCell::new(GO::Obstacle(obstacle))));
+        true
+    }
+
     pub fn update(&mut self, agents: &[Object], tiles: &[Object], holes: &[Object]) {
-        for a in agents {
+        self.update_with(agents, tiles, holes, &[]);
+    }
+
+    /// Like `update`, but agents with an action carry it out instead of
+    /// planning their own moves. `actions` is in the same order as `agents`.
+    pub fn update_with(
+        &mut self,
+        agents: &[Object],
+        tiles: &[Object],
+        holes: &[Object],
+        actions: &[Option<Action>],
+    ) {
+        self.tick += 1;
+        let (tiles, holes) = (&self.remaining(tiles), &self.remaining(holes));
+        if let Some(bus) = &mut self.comms {
+            bus.start_tick(self.tick);
+        }
+        for o in self.dynamic.clone() {
+            if let GO::Obstacle(ref mut obstacle) = *o.borrow_mut() {
+                obstacle.update(self, o.clone());
+            }
+        }
+        for (i, a) in agents.iter().enumerate() {
             if let GO::Agent(ref mut agent) = *a.borrow_mut() {
-                agent.update(self, a.clone(), tiles, holes);
+                match actions.get(i).copied().flatten() {
+                    Some(action) => agent.act(self, a.clone(), action, tiles, holes),
+                    None => agent.update(self, a.clone(), tiles, holes),
+                }
             }
         }
     }
@@ -71,11 +467,10 @@ impl Grid {
         let mut agents = vec![];
         let mut tiles = vec![];
         let mut holes = vec![];
-        let mut rng = rand::rng();
         for i in 1..=num_agents {
             let l = self.random_location().expect("Grid full: cannot place agent");
             let agent = AgentState::new(l, i);
-            let r = Rc::new(RefCell::new(GO::Agent(agent)));
+            let r = Rc::new(RefCell::new(GO::Agent(Box::new(agent))));
             agents.push(r.clone());
             self.objects.insert(l, r);
         }
@@ -83,7 +478,7 @@ impl Grid {
             let l = self.random_location().expect("Grid full: cannot place tile");
             let tile = TileState {
                 location: l,
-                score: rng.random_range(1..6),
+                score: self.rng().random_range(1..6),
             };
             let r = Rc::new(RefCell::new(GO::Tile(tile)));
             tiles.push(r.clone());
@@ -95,13 +490,58 @@ impl Grid {
             holes.push(r.clone());
             self.objects.insert(l, r);
         }
-        for _i in 1..=num_obstacles {
-            let l = self.random_location().expect("Grid full: cannot place obstacle");
-            let r = Rc::new(RefCell::new(GO::Obstacle(l)));
-            self.objects.insert(l, r);
+        // obstacles go last and only where they keep the grid connected, so
+        // every agent can reach every tile and hole
+        for i in 1..=num_obstacles {
+            if !self.place_connected_obstacle() {
+                warn!("placed only {} of {num_obstacles} obstacles", i - 1);
+                break;
+            }
         }
+        debug_assert!(self.is_connected());
         (agents, tiles, holes)
     }
+
+    /// Fill the grid with the static obstacles of a generated layout. Call this
+    /// before `create_objects` so the objects end up in the open cells.
+    pub fn create_layout(&mut self, generator: Generator) {
+        let walls = generator.generate(&mut *self.rng());
+        for l in walls {
+            let obstacle = ObstacleState::new(l, ObstacleKind::Static);
+            self.objects
+                .insert(l, Rc::new(RefCell::new(GO::Obstacle(obstacle))));
+        }
+    }
+
+    /// Place `count` obstacles of the given kind where none of them alone
+    /// would cut off part of the grid. Doors and blinking obstacles get a
+    /// random phase so they don't all switch on the same tick.
+    pub fn create_dynamic_obstacles(&mut self, kind: ObstacleKind, count: u8) {
+        for i in 1..=count {
+            let mut rng = self.rng();
+            let kind = match kind {
+                ObstacleKind::Door { period, .. } => ObstacleKind::Door {
+                    period,
+                    phase: rng.random_range(0..2 * period),
+                    open: false,
+                },
+                ObstacleKind::Blinking { period, .. } => ObstacleKind::Blinking {
+                    period,
+                    phase: rng.random_range(0..2 * period),
+                    visible: true,
+                },
+                k => k,
+            };
+            drop(rng);
+            let Some(l) = self.random_connected_location() else {
+                warn!("placed only {} of {count} dynamic obstacles", i - 1);
+                break;
+            };
+            let r = Rc::new(RefCell::new(GO::Obstacle(ObstacleState::new(l, kind))));
+            self.dynamic.push(r.clone());
+            self.objects.insert(l, r);
+        }
+    }
 }
 
 #[cfg(test)]
@@ -109,6 +549,32 @@ mod tests {
     use super::*;
     use crate::objects::State;
 
+    /// Put `go` on the grid at its location, for tests that need things in
+    /// particular places
+    fn put(grid: &mut Grid, go: GO) -> Object {
+        let l = *go.location();
+        let o = Rc::new(RefCell::new(go));
+        grid.insert_object(o.clone(), l);
+        o
+    }
+
+    fn agent_at(grid: &mut Grid, col: u16, row: u16, id: u8) -> Object {
+        put(
+            grid,
+            GO::Agent(Box::new(AgentState::new(Location::new(col, row), id))),
+        )
+    }
+
+    fn tile_at(grid: &mut Grid, col: u16, row: u16, score: u32) -> Object {
+        put(
+            grid,
+            GO::Tile(TileState {
+                location: Location::new(col, row),
+                score,
+            }),
+        )
+    }
+
     #[test]
     fn test_grid_new_is_empty() {
         let grid = Grid::new();
@@ -127,8 +593,9 @@ mod tests {
 
         assert!(!grid.is_free(loc));
         assert!(grid.object(loc).is_some());
-        if let GO::Obstacle(l) = *grid.object(loc).unwrap().borrow() {
-            assert_eq!(l, loc);
+        if let GO::Obstacle(ref o) = *grid.object(loc).unwrap().borrow() {
+            assert_eq!(o.location, loc);
+            assert_eq!(o.kind, ObstacleKind::Static);
         } else {
             panic!("Expected obstacle");
         }
@@ -252,4 +719,497 @@ mod tests {
         // All objects should have unique locations (20 total, not counting obstacles in returned vecs)
         assert_eq!(locations.len(), 15); // 5 agents + 5 tiles + 5 holes
     }
+
+    #[test]
+    fn test_door_opens_and_closes() {
+        let mut grid = Grid::new();
+        grid.create_dynamic_obstacles(
+            ObstacleKind::Door {
+                period: 2,
+                phase: 0,
+                open: false,
+            },
+            1,
+        );
+        let door = grid.dynamic_obstacles()[0].clone();
+        let loc = *door.borrow().location();
+
+        let mut open_ticks = 0;
+        for _ in 0..8 {
+            grid.update(&[], &[], &[]);
+            let present = if let GO::Obstacle(ref o) = *door.borrow() {
+                o.is_present()
+            } else {
+                panic!("Expected obstacle");
+            };
+            assert_eq!(present, !grid.is_free(loc));
+            if !present {
+                open_ticks += 1;
+            }
+        }
+        // period 2: open for 2 ticks out of every 4
+        assert_eq!(open_ticks, 4);
+    }
+
+    #[test]
+    fn test_door_stays_open_while_occupied() {
+        let mut grid = Grid::new();
+        grid.create_dynamic_obstacles(
+            ObstacleKind::Door {
+                period: 1,
+                phase: 0,
+                open: false,
+            },
+            1,
+        );
+        let door = grid.dynamic_obstacles()[0].clone();
+        let loc = *door.borrow().location();
+        // advance until the door is open, then block it with an obstacle
+        while !grid.is_free(loc) {
+            grid.update(&[], &[], &[]);
+        }
+        grid.add_obstacle(loc);
+        for _ in 0..4 {
+            grid.update(&[], &[], &[]);
+            if let GO::Obstacle(ref o) = *door.borrow() {
+                assert!(!o.is_present());
+            }
+        }
+    }
+
+    #[test]
+    fn test_wandering_obstacle_moves_to_free_cells() {
+        let mut grid = Grid::new();
+        grid.create_dynamic_obstacles(ObstacleKind::Wandering, 3);
+        for _ in 0..50 {
+            grid.update(&[], &[], &[]);
+            for o in grid.dynamic_obstacles() {
+                let loc = *o.borrow().location();
+                let on_grid = grid.object(loc).expect("obstacle should be on the grid");
+                assert!(Rc::ptr_eq(on_grid, o));
+            }
+        }
+    }
+
+    #[test]
+    fn test_blinking_obstacle_vanishes_and_reappears() {
+        let mut grid = Grid::new();
+        grid.create_dynamic_obstacles(
+            ObstacleKind::Blinking {
+                period: 1,
+                phase: 0,
+                visible: true,
+            },
+            1,
+        );
+        let blinker = grid.dynamic_obstacles()[0].clone();
+        let mut seen_present = false;
+        let mut seen_absent = false;
+        for _ in 0..6 {
+            grid.update(&[], &[], &[]);
+            let loc = *blinker.borrow().location();
+            if grid.is_free(loc) {
+                seen_absent = true;
+            } else {
+                seen_present = true;
+            }
+        }
+        assert!(seen_present && seen_absent);
+    }
+
+    #[test]
+    fn test_terrain_defaults_to_road() {
+        let mut grid = Grid::new();
+        let loc = Location::new(3, 3);
+        assert_eq!(grid.terrain(loc), Terrain::Road);
+        grid.set_terrain(loc, Terrain::Mud);
+        assert_eq!(grid.terrain(loc), Terrain::Mud);
+        grid.set_terrain(loc, Terrain::Road);
+        assert_eq!(grid.terrain(loc), Terrain::Road);
+    }
+
+    #[test]
+    fn test_generate_terrain() {
+        let mut grid = Grid::new();
+        grid.generate_terrain(5);
+        let rough = (0..ROWS)
+            .flat_map(|r| (0..COLS).map(move |c| Location::new(c, r)))
+            .filter(|&l| grid.terrain(l) != Terrain::Road)
+            .count();
+        assert!(rough > 0);
+    }
+
+    #[test]
+    fn test_can_move_diagonal_only_with_eight_movement() {
+        let mut grid = Grid::new();
+        let loc = Location::new(5, 5);
+        assert!(grid.can_move(loc, Direction::Up));
+        assert!(!grid.can_move(loc, Direction::UpLeft));
+        grid.set_movement(Movement::Eight);
+        assert!(grid.can_move(loc, Direction::UpLeft));
+    }
+
+    #[test]
+    fn test_no_corner_cutting() {
+        let mut grid = Grid::new();
+        grid.set_movement(Movement::Octile);
+        let loc = Location::new(5, 5);
+        grid.add_obstacle(Location::new(5, 4));
+        assert!(!grid.can_move(loc, Direction::UpLeft));
+        assert!(!grid.can_move(loc, Direction::UpRight));
+        assert!(grid.can_move(loc, Direction::DownLeft));
+        assert!(grid.can_move(loc, Direction::DownRight));
+    }
+
+    #[test]
+    fn test_step_cost() {
+        let mut grid = Grid::new();
+        let loc = Location::new(5, 5);
+        grid.set_terrain(Location::new(6, 6), Terrain::Mud);
+        grid.set_movement(Movement::Eight);
+        assert_eq!(grid.step_cost(loc, Direction::DownRight), 30);
+        grid.set_movement(Movement::Octile);
+        assert_eq!(grid.step_cost(loc, Direction::DownRight), 42);
+        assert_eq!(grid.step_cost(loc, Direction::Down), 10);
+    }
+
+    #[test]
+    fn test_torus_wraps_around() {
+        let mut grid = Grid::new();
+        let corner = Location::new(0, 0);
+        assert!(!grid.can_move(corner, Direction::Up));
+        assert_eq!(
+            grid.estimate(corner, Location::new(COLS - 1, 0)),
+            10 * u32::from(COLS - 1)
+        );
+        grid.set_topology(Topology::Torus);
+        assert!(grid.can_move(corner, Direction::Up));
+        assert_eq!(
+            grid.next_location(corner, Direction::Up),
+            Location::new(0, ROWS - 1)
+        );
+        assert_eq!(grid.estimate(corner, Location::new(COLS - 1, 0)), 10);
+    }
+
+    #[test]
+    fn test_reachable_from_walled_pocket() {
+        let mut grid = Grid::new();
+        // wall in the top left corner
+        grid.add_obstacle(Location::new(1, 0));
+        grid.add_obstacle(Location::new(0, 1));
+        grid.add_obstacle(Location::new(1, 1));
+        let pocket = grid.reachable_from(Location::new(0, 0));
+        assert_eq!(pocket.len(), 1);
+        assert!(!grid.is_connected());
+    }
+
+    #[test]
+    fn test_diagonal_does_not_squeeze_through_corner() {
+        let mut grid = Grid::new();
+        grid.set_movement(Movement::Eight);
+        grid.add_obstacle(Location::new(1, 0));
+        grid.add_obstacle(Location::new(0, 1));
+        assert_eq!(grid.reachable_from(Location::new(0, 0)).len(), 1);
+    }
+
+    #[test]
+    fn test_create_objects_keeps_grid_connected() {
+        let mut grid = Grid::new();
+        let (agents, tiles, holes) = grid.create_objects(6, 20, 20, 255);
+        assert!(grid.is_connected());
+        let start = *agents[0].borrow().location();
+        let reachable = grid.reachable_from(start);
+        for o in agents.iter().chain(&tiles).chain(&holes) {
+            assert!(reachable.contains(o.borrow().location()));
+        }
+    }
+
+    /// A wall down the middle with a door in it, and a room in the top left
+    /// corner with a single way in
+    fn walled_grid() -> Grid {
+        let mut grid = Grid::new();
+        for r in (0..ROWS).filter(|&r| r != 20) {
+            grid.add_obstacle(Location::new(20, r));
+        }
+        for i in 0..5 {
+            grid.add_obstacle(Location::new(i, 4));
+            grid.add_obstacle(Location::new(4, i));
+        }
+        grid.remove_object(Location::new(2, 4));
+        grid
+    }
+
+    #[test]
+    fn test_cut_cells() {
+        for topology in [Topology::Bounded, Topology::Torus] {
+            let mut grid = walled_grid();
+            grid.set_topology(topology);
+            let cut = grid.cut_cells();
+            // around the edges of a torus there are other ways in
+            let bounded = topology == Topology::Bounded;
+            assert_eq!(cut.contains(&Location::new(2, 4)), bounded);
+            assert_eq!(cut.contains(&Location::new(20, 20)), bounded);
+            // the same cells as walling in each one and checking the whole
+            // grid, around the walls and across the edges
+            for r in (0..8).chain(ROWS - 2..ROWS) {
+                for c in (0..8).chain(18..23).chain(COLS - 2..COLS) {
+                    let l = Location::new(c, r);
+                    if grid.is_free(l) {
+                        grid.add_obstacle(l);
+                        assert_eq!(
+                            cut.contains(&l),
+                            !grid.is_connected(),
+                            "{l:?} on {topology:?}"
+                        );
+                        grid.remove_object(l);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_dynamic_obstacles_keep_grid_connected() {
+        let mut grid = walled_grid();
+        let cut = grid.cut_cells();
+        let kind = ObstacleKind::Blinking {
+            period: 2,
+            phase: 0,
+            visible: true,
+        };
+        grid.create_dynamic_obstacles(kind, 50);
+        assert_eq!(grid.dynamic_obstacles().len(), 50);
+        for _ in 0..10 {
+            grid.update(&[], &[], &[]);
+            for o in grid.dynamic_obstacles() {
+                let o = o.borrow();
+                assert!(
+                    !cut.contains(o.location()),
+                    "{:?} walls off part of the grid",
+                    o.location()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_create_layout_then_objects() {
+        let mut grid = Grid::new();
+        grid.create_layout(Generator::Maze);
+        assert!(grid.is_connected());
+        let (agents, tiles, _) = grid.create_objects(2, 5, 5, 0);
+        for o in agents.iter().chain(&tiles) {
+            assert!(!grid.is_wall(*o.borrow().location()));
+        }
+    }
+
+    #[test]
+    fn test_agent_explores_until_tile_in_sight() {
+        let mut grid = Grid::new();
+        grid.set_sensor(Sensor::Radius {
+            radius: 3,
+            line_of_sight: false,
+        });
+        let agents = [agent_at(&mut grid, 5, 5, 1), agent_at(&mut grid, 20, 20, 2)];
+        // one tile next to agent 2, one that neither can see
+        let tiles = [tile_at(&mut grid, 21, 21, 3), tile_at(&mut grid, 35, 5, 3)];
+        let holes = [put(
+            &mut grid,
+            GO::Hole(HoleState {
+                location: Location::new(5, 35),
+            }),
+        )];
+        grid.update(&agents, &tiles, &holes);
+        let state = |a: &Object| match *a.borrow() {
+            GO::Agent(ref a) => a.state,
+            _ => unreachable!(),
+        };
+        assert_eq!(state(&agents[0]), State::Explore);
+        assert_eq!(state(&agents[1]), State::MoveToTile);
+        // wherever exploring took it, a tile that comes into view is next
+        for _ in 0..10 {
+            grid.update(&agents, &tiles, &holes);
+        }
+        let here = *agents[0].borrow().location();
+        let near = DIRECTIONS
+            .into_iter()
+            .map(|d| grid.next_location(here, d))
+            .find(|&l| grid.is_free(l))
+            .unwrap();
+        let tiles = [
+            tiles[0].clone(),
+            tiles[1].clone(),
+            tile_at(&mut grid, near.col, near.row, 3),
+        ];
+        grid.update(&agents, &tiles, &holes);
+        if let GO::Agent(ref a) = *agents[0].borrow() {
+            assert!(a.state == State::MoveToTile || a.has_tile);
+        }
+    }
+
+    #[test]
+    fn test_agent_with_memory_delivers_tiles() {
+        let mut grid = Grid::new();
+        grid.set_sensor(Sensor::Radius {
+            radius: 4,
+            line_of_sight: true,
+        });
+        grid.set_memory(true);
+        let agents = [agent_at(&mut grid, 5, 5, 1)];
+        let tiles = [tile_at(&mut grid, 8, 5, 3)];
+        let hole = Location::new(5, 8);
+        let holes = [put(&mut grid, GO::Hole(HoleState { location: hole }))];
+        // the wall hides the hole once the agent is at the tile
+        for r in 6..=8 {
+            grid.add_obstacle(Location::new(7, r));
+        }
+        for _ in 0..20 {
+            grid.update(&agents, &tiles, &holes);
+        }
+        assert!(agents[0].borrow().score() >= 3);
+        if let GO::Agent(ref a) = *agents[0].borrow() {
+            assert!(a.belief.cell(a.location).is_some());
+            assert!(a.belief.cell(Location::new(7, 7)).is_some());
+        }
+    }
+
+    #[test]
+    fn test_agents_share_what_they_see() {
+        let mut grid = Grid::new();
+        grid.set_sensor(Sensor::Radius {
+            radius: 3,
+            line_of_sight: false,
+        });
+        grid.set_comms(CommsConfig::default());
+        assert!(grid.memory());
+        let agents = [agent_at(&mut grid, 5, 5, 1), agent_at(&mut grid, 30, 30, 2)];
+        let tile = Location::new(6, 5);
+        let tiles = [tile_at(&mut grid, 6, 5, 3)];
+        let holes = [put(
+            &mut grid,
+            GO::Hole(HoleState {
+                location: Location::new(5, 7),
+            }),
+        )];
+        grid.update(&agents, &tiles, &holes);
+        // agent 1 tells about the tile and the hole and claims the tile
+        let stats = grid.comms().unwrap().stats();
+        assert_eq!((stats.sent, stats.delivered), (3, 3));
+        assert_eq!(stats.lost + stats.out_of_range + stats.over_bandwidth, 0);
+        if let GO::Agent(ref a) = *agents[1].borrow() {
+            assert!(a.belief.cell(tile).is_some());
+        }
+    }
+
+    #[test]
+    fn test_messages_over_bandwidth_are_sent_later() {
+        let mut grid = Grid::new();
+        grid.set_comms(CommsConfig {
+            bandwidth: Some(1),
+            ..CommsConfig::default()
+        });
+        let agents = [agent_at(&mut grid, 5, 5, 1), agent_at(&mut grid, 30, 30, 2)];
+        let tiles = [tile_at(&mut grid, 10, 10, 3)];
+        let holes = [put(
+            &mut grid,
+            GO::Hole(HoleState {
+                location: Location::new(20, 20),
+            }),
+        )];
+        // each agent sees the tile and the hole at once, but gets one message out
+        grid.update(&agents, &tiles, &holes);
+        let stats = grid.comms().unwrap().stats();
+        assert_eq!((stats.sent, stats.over_bandwidth), (2, 2));
+        grid.update(&agents, &tiles, &holes);
+        grid.update(&agents, &tiles, &holes);
+        // what didn't fit went out one per tick
+        let stats = grid.comms().unwrap().stats();
+        assert_eq!(stats.sent, 5);
+    }
+
+    #[test]
+    fn test_tiles_are_used_up_without_respawn() {
+        let mut grid = Grid::new();
+        grid.set_respawn(false);
+        let agents = [agent_at(&mut grid, 5, 5, 1), agent_at(&mut grid, 30, 30, 2)];
+        let tiles = [
+            tile_at(&mut grid, 10, 5, 1),
+            tile_at(&mut grid, 25, 30, 2),
+            tile_at(&mut grid, 20, 20, 4),
+        ];
+        let holes: Vec<Object> = [(5, 10), (30, 25), (15, 15), (35, 35), (2, 38)]
+            .into_iter()
+            .map(|(c, r)| {
+                put(
+                    &mut grid,
+                    GO::Hole(HoleState {
+                        location: Location::new(c, r),
+                    }),
+                )
+            })
+            .collect();
+        for _ in 0..200 {
+            grid.update(&agents, &tiles, &holes);
+        }
+        // every tile went into a hole, and then they are gone
+        assert!(grid.remaining(&tiles).is_empty());
+        assert_eq!(grid.remaining(&holes).len(), 2);
+        let total: u32 = agents.iter().map(|a| a.borrow().score()).sum();
+        assert_eq!(total, 7);
+    }
+
+    #[test]
+    fn test_controlled_agent_scores() {
+        let mut grid = Grid::new();
+        let (agents, tiles, holes) = grid.create_objects(1, 1, 1, 0);
+        // steer the agent along the shortest path to the tile, then to the hole
+        for (target, action) in [(&tiles[0], Action::Pick), (&holes[0], Action::Drop)] {
+            let from = *agents[0].borrow().location();
+            let to = *target.borrow().location();
+            for d in crate::astar::astar(&grid, from, to).unwrap() {
+                grid.update_with(&agents, &tiles, &holes, &[Some(Action::Move(d))]);
+            }
+            grid.update_with(&agents, &tiles, &holes, &[Some(action)]);
+        }
+        if let GO::Agent(ref a) = *agents[0].borrow() {
+            assert!(!a.has_tile);
+            assert_eq!(a.score, tiles[0].borrow().score());
+        }
+    }
+
+    #[test]
+    fn test_controlled_agent_cannot_walk_into_obstacle() {
+        let mut grid = Grid::new();
+        let (agents, tiles, holes) = grid.create_objects(1, 0, 0, 0);
+        let from = *agents[0].borrow().location();
+        let d = if from.col > 0 {
+            Direction::Left
+        } else {
+            Direction::Right
+        };
+        grid.add_obstacle(grid.next_location(from, d));
+        grid.update_with(&agents, &tiles, &holes, &[Some(Action::Move(d))]);
+        assert_eq!(*agents[0].borrow().location(), from);
+    }
+
+    #[test]
+    fn test_controlled_agent_walks_over_tile() {
+        let mut grid = Grid::new();
+        let (agents, tiles, holes) = grid.create_objects(1, 1, 0, 0);
+        let tile = *tiles[0].borrow().location();
+        let path = crate::astar::astar(&grid, *agents[0].borrow().location(), tile).unwrap();
+        for &d in &path {
+            grid.update_with(&agents, &tiles, &holes, &[Some(Action::Move(d))]);
+        }
+        assert_eq!(*agents[0].borrow().location(), tile);
+        // stepping back off leaves the tile where it was
+        let back = match path.last() {
+            Some(Direction::Up) => Direction::Down,
+            Some(Direction::Down) => Direction::Up,
+            Some(Direction::Left) => Direction::Right,
+            _ => Direction::Left,
+        };
+        grid.update_with(&agents, &tiles, &holes, &[Some(Action::Move(back))]);
+        assert!(matches!(*grid.object(tile).unwrap().borrow(), GO::Tile(_)));
+    }
 }
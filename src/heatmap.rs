@@ -0,0 +1,189 @@
+use crate::{COLS, ROWS, grid::Grid, location::Location, raster::Canvas};
+use graphics::Rectangle;
+use std::{io, path::Path};
+
+/// Pixels per cell in the saved image
+const CELL: u32 = 12;
+/// Pixels between the two layers in the saved image
+const GAP: u32 = 12;
+
+/// What a heatmap shows
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Layer {
+    #[default]
+    Off,
+    /// How often agents stepped onto each cell
+    Visits,
+    /// How often agents on each cell tried to move and couldn't
+    Blocked,
+}
+
+impl Layer {
+    pub fn next(self) -> Self {
+        match self {
+            Layer::Off => Layer::Visits,
+            Layer::Visits => Layer::Blocked,
+            Layer::Blocked => Layer::Off,
+        }
+    }
+}
+
+/// Visits and blocked moves per cell, counted over a whole run to find the
+/// corridors agents crowd into and the places they get stuck
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Heatmap {
+    visits: Vec<u32>,
+    blocked: Vec<u32>,
+}
+
+impl Default for Heatmap {
+    fn default() -> Self {
+        let cells = usize::from(ROWS) * usize::from(COLS);
+        Heatmap {
+            visits: vec![0; cells],
+            blocked: vec![0; cells],
+        }
+    }
+}
+
+impl Heatmap {
+    pub fn new() -> Self {
+        Heatmap::default()
+    }
+
+    fn index(l: Location) -> usize {
+        usize::from(l.row) * usize::from(COLS) + usize::from(l.col)
+    }
+
+    fn counts(&self, layer: Layer) -> &[u32] {
+        match layer {
+            Layer::Off => &[],
+            Layer::Visits => &self.visits,
+            Layer::Blocked => &self.blocked,
+        }
+    }
+
+    /// An agent stepped onto `l`
+    pub fn visit(&mut self, l: Location) {
+        self.visits[Heatmap::index(l)] += 1;
+    }
+
+    /// An agent on `l` tried to move and couldn't
+    pub fn block(&mut self, l: Location) {
+        self.blocked[Heatmap::index(l)] += 1;
+    }
+
+    pub fn get(&self, layer: Layer, l: Location) -> u32 {
+        self.counts(layer)
+            .get(Heatmap::index(l))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    pub fn max(&self, layer: Layer) -> u32 {
+        self.counts(layer).iter().copied().max().unwrap_or_default()
+    }
+
+    /// The cells counted at least once and how hot they are, from 0 to 1.
+    /// The scale is logarithmic so a few busy cells don't wash out the rest.
+    pub fn hot_cells(&self, layer: Layer) -> Vec<(Location, f32)> {
+        let max = (1.0 + self.max(layer) as f32).ln();
+        let mut cells = vec![];
+        for row in 0..ROWS {
+            for col in 0..COLS {
+                let l = Location::new(col, row);
+                let count = self.get(layer, l);
+                if count > 0 {
+                    cells.push((l, (1.0 + count as f32).ln() / max));
+                }
+            }
+        }
+        cells
+    }
+
+    /// Save both layers side by side as a PNG, visits on the left and blocked
+    /// moves on the right, with the obstacles of `g` in grey
+    pub fn save_png(&self, g: &Grid, path: &Path) -> io::Result<()> {
+        let mut canvas = Canvas::new(2 * u32::from(COLS) * CELL + GAP, u32::from(ROWS) * CELL);
+        let ctx = canvas.context();
+        let size = f64::from(CELL);
+        for (layer, left) in [
+            (Layer::Visits, 0.0),
+            (Layer::Blocked, f64::from(COLS) * size + f64::from(GAP)),
+        ] {
+            let mut fill = |l: Location, [red, green, blue]: [f32; 3]| {
+                let cell = [
+                    left + f64::from(l.col) * size,
+                    f64::from(l.row) * size,
+                    size,
+                    size,
+                ];
+                Rectangle::new([red, green, blue, 1.0]).draw(
+                    cell,
+                    &ctx.draw_state,
+                    ctx.transform,
+                    &mut canvas,
+                );
+            };
+            for (l, heat) in self.hot_cells(layer) {
+                fill(l, heat_color(heat));
+            }
+            for row in 0..ROWS {
+                for col in 0..COLS {
+                    let l = Location::new(col, row);
+                    if g.is_obstacle(l) {
+                        fill(l, [0.4; 3]);
+                    }
+                }
+            }
+        }
+        canvas.save_png(path)
+    }
+}
+
+/// White for cold cells, through yellow to red for the hottest
+pub fn heat_color(heat: f32) -> [f32; 3] {
+    let heat = heat.clamp(0.0, 1.0);
+    if heat < 0.5 {
+        [1.0, 1.0, 1.0 - 2.0 * heat]
+    } else {
+        [1.0, 2.0 - 2.0 * heat, 0.0]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counts_and_heat() {
+        let mut heatmap = Heatmap::new();
+        let (busy, quiet) = (Location::new(3, 4), Location::new(5, 4));
+        for _ in 0..9 {
+            heatmap.visit(busy);
+        }
+        heatmap.visit(quiet);
+        heatmap.block(quiet);
+        assert_eq!(heatmap.get(Layer::Visits, busy), 9);
+        assert_eq!(heatmap.max(Layer::Blocked), 1);
+        let cells = heatmap.hot_cells(Layer::Visits);
+        assert_eq!(cells[0], (busy, 1.0));
+        assert_eq!(cells[1].0, quiet);
+        assert!((0.0..1.0).contains(&cells[1].1));
+        assert_eq!(heatmap.hot_cells(Layer::Blocked), vec![(quiet, 1.0)]);
+        assert_eq!(heat_color(0.0), [1.0; 3]);
+        assert_eq!(heat_color(1.0), [1.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn test_save_png() {
+        let mut heatmap = Heatmap::new();
+        heatmap.visit(Location::new(0, 0));
+        let path =
+            std::env::temp_dir().join(format!("tileworld-heatmap-{}.png", std::process::id()));
+        heatmap.save_png(&Grid::new(), &path).unwrap();
+        let data = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(&data[1..4], b"PNG");
+    }
+}
@@ -0,0 +1,128 @@
+use crate::objects::AgentInfo;
+use std::collections::VecDeque;
+
+/// How many of the last ticks are kept for the score chart
+pub const TICKS: usize = 300;
+
+/// The scores of the agents over the last ticks of the episode, to see when
+/// a strategy stalls
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ScoreHistory {
+    /// The tick and the score of each agent in order of id, oldest first
+    ticks: VecDeque<(u64, Vec<u32>)>,
+}
+
+impl ScoreHistory {
+    pub fn new() -> Self {
+        ScoreHistory::default()
+    }
+
+    /// Keep the scores at `tick`, dropping the oldest beyond `TICKS`. When
+    /// agents were added or removed the old scores no longer line up and are
+    /// dropped too.
+    pub fn record(&mut self, tick: u64, agents: &[AgentInfo]) {
+        if self
+            .ticks
+            .back()
+            .is_some_and(|(_, scores)| scores.len() != agents.len())
+        {
+            self.ticks.clear();
+        }
+        if self.ticks.len() == TICKS {
+            self.ticks.pop_front();
+        }
+        self.ticks
+            .push_back((tick, agents.iter().map(|a| a.score).collect()));
+    }
+
+    pub fn clear(&mut self) {
+        self.ticks.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.ticks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ticks.is_empty()
+    }
+
+    /// The summed scores of the agents `include` picks by index, oldest first
+    pub fn total(&self, include: impl Fn(usize) -> bool) -> Vec<u32> {
+        self.ticks
+            .iter()
+            .map(|(_, scores)| {
+                scores
+                    .iter()
+                    .enumerate()
+                    .filter(|&(i, _)| include(i))
+                    .map(|(_, s)| s)
+                    .sum()
+            })
+            .collect()
+    }
+
+    /// Points scored by all agents per 100 ticks over the kept ticks
+    pub fn throughput(&self) -> f64 {
+        let (Some((first, before)), Some((last, after))) = (self.ticks.front(), self.ticks.back())
+        else {
+            return 0.0;
+        };
+        if last == first {
+            return 0.0;
+        }
+        // scores only go up, but an agent may have been replaced in between
+        let sum = |scores: &[u32]| scores.iter().map(|&s| i64::from(s)).sum::<i64>();
+        let points = sum(after).saturating_sub(sum(before)).max(0);
+        points as f64 * 100.0 / last.saturating_sub(*first) as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::location::Location;
+
+    fn agents(scores: &[u32]) -> Vec<AgentInfo> {
+        (1..)
+            .zip(scores)
+            .map(|(id, &score)| AgentInfo {
+                id,
+                team: id % 2,
+                score,
+                location: Location::new(0, 0),
+                has_tile: false,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_keeps_the_last_ticks() {
+        let mut history = ScoreHistory::new();
+        assert_eq!(history.throughput(), 0.0);
+        for tick in 0..TICKS as u64 + 50 {
+            history.record(tick, &agents(&[tick as u32 / 10, 1]));
+        }
+        assert_eq!(history.len(), TICKS);
+        let first = history.total(|i| i == 0);
+        assert_eq!(first[0], 5);
+        assert_eq!(first[TICKS - 1], 34);
+        assert_eq!(history.total(|_| true)[0], 6);
+        // 29 points in 299 ticks
+        assert!((history.throughput() - 2900.0 / 299.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_agents_changing_starts_over() {
+        let mut history = ScoreHistory::new();
+        history.record(0, &agents(&[0, 0]));
+        history.record(1, &agents(&[3, 0]));
+        assert_eq!(history.throughput(), 300.0);
+        history.record(2, &agents(&[3]));
+        assert_eq!(history.len(), 1);
+        assert_eq!(history.throughput(), 0.0);
+        // the same number of agents, but the one that scored was replaced
+        history.record(3, &agents(&[0]));
+        assert_eq!(history.throughput(), 0.0);
+    }
+}
@@ -0,0 +1,121 @@
+use crate::{
+    env::Env,
+    location::Direction,
+    objects::{Action, Object},
+};
+use piston::Key;
+use std::rc::Rc;
+
+/// The action for a key: the arrows move, Q, E, Z and C move diagonally
+/// (when the movement model allows it), P picks up a tile and D drops it
+pub fn key_action(key: Key) -> Option<Action> {
+    Some(match key {
+        Key::Up => Action::Move(Direction::Up),
+        Key::Down => Action::Move(Direction::Down),
+        Key::Left => Action::Move(Direction::Left),
+        Key::Right => Action::Move(Direction::Right),
+        Key::Q => Action::Move(Direction::UpLeft),
+        Key::E => Action::Move(Direction::UpRight),
+        Key::Z => Action::Move(Direction::DownLeft),
+        Key::C => Action::Move(Direction::DownRight),
+        Key::P => Action::Pick,
+        Key::D => Action::Drop,
+        _ => return None,
+    })
+}
+
+/// An agent played by a person with the keyboard. A key pressed between two
+/// ticks is done in the next one, and a move key held down keeps moving.
+#[derive(Debug)]
+pub struct Human {
+    /// The agent played, which keeps its place when others come and go
+    pub agent: Object,
+    pressed: Option<Action>,
+    held: Option<Key>,
+}
+
+impl Human {
+    pub fn new(agent: Object) -> Self {
+        Human {
+            agent,
+            pressed: None,
+            held: None,
+        }
+    }
+
+    /// Where the played agent is among the agents of `env`, None when it
+    /// isn't on the grid anymore
+    pub fn slot(&self, env: &Env) -> Option<usize> {
+        env.agents().iter().position(|a| Rc::ptr_eq(a, &self.agent))
+    }
+
+    /// Returns whether the key is one of the agent's
+    pub fn press(&mut self, key: Key) -> bool {
+        let Some(action) = key_action(key) else {
+            return false;
+        };
+        self.pressed = Some(action);
+        if let Action::Move(_) = action {
+            self.held = Some(key);
+        }
+        true
+    }
+
+    pub fn release(&mut self, key: Key) {
+        if self.held == Some(key) {
+            self.held = None;
+        }
+    }
+
+    /// The action for this tick, staying put when no key was pressed
+    pub fn action(&mut self) -> Action {
+        self.pressed
+            .take()
+            .or_else(|| self.held.and_then(key_action))
+            .unwrap_or(Action::Stay)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    #[test]
+    fn test_keys() {
+        let env = Env::new(Config::default());
+        let mut human = Human::new(env.agents()[0].clone());
+        assert_eq!(human.action(), Action::Stay);
+        assert!(!human.press(Key::B));
+        assert!(human.press(Key::P));
+        assert_eq!(human.action(), Action::Pick);
+        assert_eq!(human.action(), Action::Stay);
+        // a held arrow keeps moving, the last key pressed wins
+        human.press(Key::Left);
+        human.press(Key::D);
+        assert_eq!(human.action(), Action::Drop);
+        assert_eq!(human.action(), Action::Move(Direction::Left));
+        human.release(Key::Left);
+        assert_eq!(human.action(), Action::Stay);
+        // a tap shorter than a tick still moves
+        human.press(Key::Up);
+        human.release(Key::Up);
+        assert_eq!(human.action(), Action::Move(Direction::Up));
+    }
+
+    #[test]
+    fn test_agent_taken_off_the_grid() {
+        let mut env = Env::new(Config {
+            agents: 2,
+            ..Config::default()
+        });
+        let human = Human::new(env.agents()[1].clone());
+        assert_eq!(human.slot(&env), Some(1));
+        let l = *env.agents()[0].borrow().location();
+        env.clear(l);
+        assert_eq!(human.slot(&env), Some(0));
+        let l = *human.agent.borrow().location();
+        env.clear(l);
+        assert_eq!(human.slot(&env), None);
+    }
+}
@@ -0,0 +1,165 @@
+use crate::{
+    env::Env,
+    location::Location,
+    objects::{AgentState, GO, ObstacleKind, State},
+};
+
+/// What was clicked on. An agent stays selected when it moves on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Selection {
+    Cell(Location),
+    Agent(u8),
+}
+
+/// Shows what is on a clicked cell in the sidebar, and for an agent what it
+/// is up to
+#[derive(Debug, Default)]
+pub struct Inspector {
+    selected: Option<Selection>,
+}
+
+impl Inspector {
+    pub fn new() -> Self {
+        Inspector::default()
+    }
+
+    /// Select what is at `l`, or nothing when it is selected already
+    pub fn select(&mut self, env: &Env, l: Location) {
+        let selection = match env.agent_info().iter().find(|a| a.location == l) {
+            Some(a) => Selection::Agent(a.id),
+            None => Selection::Cell(l),
+        };
+        self.selected = (self.selected != Some(selection)).then_some(selection);
+    }
+
+    pub fn clear(&mut self) {
+        self.selected = None;
+    }
+
+    pub fn selected(&self) -> Option<Selection> {
+        self.selected
+    }
+
+    /// Where the selection is now, None when nothing (or an agent that was
+    /// removed) is selected
+    pub fn location(&self, env: &Env) -> Option<Location> {
+        match self.selected? {
+            Selection::Cell(l) => Some(l),
+            Selection::Agent(id) => env
+                .agent_info()
+                .iter()
+                .find(|a| a.id == id)
+                .map(|a| a.location),
+        }
+    }
+
+    /// The lines describing the selection
+    pub fn lines(&self, env: &Env) -> Vec<String> {
+        let Some(l) = self.location(env) else {
+            return vec![];
+        };
+        let g = env.grid();
+        let mut lines = vec![];
+        match g.object(l) {
+            Some(o) => match *o.borrow() {
+                GO::Agent(ref a) => lines.extend(describe_agent(a)),
+                GO::Tile(ref t) => lines.push(format!("Tile of {} at {}", t.score, cell(l))),
+                GO::Hole(_) => lines.push(format!("Hole at {}", cell(l))),
+                GO::Obstacle(ref o) => {
+                    lines.push(format!("{} at {}", obstacle_name(o.kind), cell(l)))
+                }
+            },
+            None => {
+                let door = g.dynamic_obstacles().iter().any(|o| {
+                    matches!(*o.borrow(), GO::Obstacle(ref o) if o.location == l && matches!(o.kind, ObstacleKind::Door { .. }))
+                });
+                let what = if door { "Open door" } else { "Empty cell" };
+                lines.push(format!("{what} at {}", cell(l)));
+            }
+        }
+        let terrain = g.terrain(l);
+        lines.push(format!("Terrain: {terrain:?}, cost {}", terrain.cost()));
+        lines
+    }
+}
+
+fn describe_agent(a: &AgentState) -> Vec<String> {
+    let what = match a.state {
+        State::MoveToTile => "tile at",
+        State::MoveToHole => "hole at",
+        _ => "exploring to",
+    };
+    let target = a.target().map(|l| format!("Target: {what} {}", cell(l)));
+    let carrying = match a.tile {
+        Some(ref t) if a.has_tile => format!("Carrying a tile of {}", t.borrow().score()),
+        _ => "Carrying nothing".to_string(),
+    };
+    let stats = a.stats;
+    vec![
+        format!("Agent({}) of team {} at {}", a.id, a.team, cell(a.location)),
+        format!("State: {:?}", a.state),
+        target.unwrap_or_else(|| "Target: none".to_string()),
+        format!("Path: {} steps left", a.path_len()),
+        carrying,
+        format!("Score: {}", a.score),
+        format!(
+            "Moved {} cells, waited {}, blocked {}",
+            stats.steps, stats.waited, stats.blocked
+        ),
+        format!("Picked {}, dropped {}", stats.picked, stats.dropped),
+        format!("Planned {} paths", stats.plans),
+    ]
+}
+
+fn obstacle_name(kind: ObstacleKind) -> &'static str {
+    match kind {
+        ObstacleKind::Static => "Obstacle",
+        ObstacleKind::Wandering => "Wandering obstacle",
+        ObstacleKind::Door { .. } => "Closed door",
+        ObstacleKind::Blinking { .. } => "Blinking obstacle",
+    }
+}
+
+fn cell(l: Location) -> String {
+    format!("({}, {})", l.col, l.row)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::Config, map::Item};
+
+    #[test]
+    fn test_inspect_agent_and_cells() {
+        let mut env = Env::new(Config {
+            agents: 1,
+            ..Config::default()
+        });
+        let mut inspector = Inspector::new();
+        let agent = env.agent_info()[0].location;
+        inspector.select(&env, agent);
+        assert_eq!(inspector.selected(), Some(Selection::Agent(1)));
+        for _ in 0..5 {
+            env.step(&[]);
+        }
+        // the selection follows the agent
+        let lines = inspector.lines(&env);
+        assert!(lines[0].starts_with("Agent(1) of team 1"));
+        assert!(lines[0].ends_with(&cell(env.agent_info()[0].location)));
+        assert!(lines.iter().any(|l| l.starts_with("State: ")));
+
+        let free = env.grid().random_location().unwrap();
+        inspector.select(&env, free);
+        assert_eq!(
+            inspector.lines(&env)[0],
+            format!("Empty cell at {}", cell(free))
+        );
+        env.place(free, Item::Tile(4));
+        assert_eq!(
+            inspector.lines(&env)[0],
+            format!("Tile of 4 at {}", cell(free))
+        );
+        inspector.select(&env, free);
+        assert!(inspector.lines(&env).is_empty());
+    }
+}
@@ -0,0 +1,41 @@
+//! TileWorld: agents carry tiles into holes on a grid full of obstacles.
+//!
+//! The binary shows the simulation in a window, `env::Env` runs it headless
+//! behind a reset/step interface for training agents, and `raster` draws it
+//! into images without a window.
+
+pub mod astar;
+pub mod belief;
+pub mod clock;
+pub mod comms;
+pub mod config;
+pub mod draw;
+pub mod editor;
+pub mod env;
+pub mod episode;
+pub mod external;
+pub mod game;
+pub mod grid;
+pub mod heatmap;
+pub mod history;
+pub mod human;
+pub mod inspector;
+pub mod location;
+pub mod map;
+pub mod mapgen;
+pub mod objects;
+pub mod protocol;
+pub mod qlearning;
+pub mod raster;
+pub mod sensor;
+pub mod server;
+pub mod terrain;
+pub mod tui;
+pub mod view;
+
+pub const COLS: u16 = 40;
+pub const ROWS: u16 = 40;
+pub const MAG: f64 = 20.0;
+
+/// The font of all text, in the window and in saved images
+pub const FONT: &[u8] = include_bytes!("../UbuntuMono-R.ttf");
@@ -1,4 +1,5 @@
 use crate::{COLS, ROWS};
+use std::{fmt, str::FromStr};
 
 #[derive(Hash, PartialEq, Eq, Debug, Clone, Copy)]
 pub enum Direction {
@@ -6,6 +7,215 @@ pub enum Direction {
     Down,
     Left,
     Right,
+    UpLeft,
+    UpRight,
+    DownLeft,
+    DownRight,
+}
+
+/// The four straight directions
+pub const DIRECTIONS: [Direction; 4] = [
+    Direction::Up,
+    Direction::Down,
+    Direction::Left,
+    Direction::Right,
+];
+
+/// Straight and diagonal directions
+pub const ALL_DIRECTIONS: [Direction; 8] = [
+    Direction::Up,
+    Direction::Down,
+    Direction::Left,
+    Direction::Right,
+    Direction::UpLeft,
+    Direction::UpRight,
+    Direction::DownLeft,
+    Direction::DownRight,
+];
+
+/// Cost of a straight step over road, in tenths of a tick
+pub const STRAIGHT_COST: u32 = 10;
+/// Cost of a diagonal step over road with octile movement (√2 ≈ 1.4)
+pub const DIAGONAL_COST: u32 = 14;
+
+impl Direction {
+    /// The two straight directions a diagonal is made of
+    pub fn components(self) -> Option<(Direction, Direction)> {
+        match self {
+            Direction::UpLeft => Some((Direction::Up, Direction::Left)),
+            Direction::UpRight => Some((Direction::Up, Direction::Right)),
+            Direction::DownLeft => Some((Direction::Down, Direction::Left)),
+            Direction::DownRight => Some((Direction::Down, Direction::Right)),
+            _ => None,
+        }
+    }
+
+    pub fn is_diagonal(self) -> bool {
+        self.components().is_some()
+    }
+}
+
+/// Which neighbouring cells an agent can step to, and what a step costs
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Movement {
+    /// Up, down, left and right; distances are Manhattan
+    #[default]
+    Four,
+    /// Diagonals too, at the same cost as a straight step; distances are Chebyshev
+    Eight,
+    /// Diagonals too, at √2 times the cost of a straight step; distances are octile
+    Octile,
+}
+
+impl Movement {
+    pub fn directions(self) -> &'static [Direction] {
+        match self {
+            Movement::Four => &DIRECTIONS,
+            Movement::Eight | Movement::Octile => &ALL_DIRECTIONS,
+        }
+    }
+
+    /// Cost of a step in direction `d` over road, in tenths of a tick
+    pub fn step_cost(self, d: Direction) -> u32 {
+        if self == Movement::Octile && d.is_diagonal() {
+            DIAGONAL_COST
+        } else {
+            STRAIGHT_COST
+        }
+    }
+
+    /// Cost of the cheapest possible route between two locations `col_diff`
+    /// columns and `row_diff` rows apart (ignoring obstacles and terrain), in
+    /// tenths of a tick. This is the A* heuristic.
+    pub fn estimate(self, col_diff: u16, row_diff: u16) -> u32 {
+        let (col_diff, row_diff) = (u32::from(col_diff), u32::from(row_diff));
+        let diagonal = col_diff.min(row_diff);
+        let straight = col_diff.max(row_diff) - diagonal;
+        match self {
+            Movement::Four => STRAIGHT_COST * (col_diff + row_diff),
+            Movement::Eight => STRAIGHT_COST * (diagonal + straight),
+            Movement::Octile => DIAGONAL_COST * diagonal + STRAIGHT_COST * straight,
+        }
+    }
+}
+
+/// What happens at the edges of the grid
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Topology {
+    /// Moving off the grid is not possible
+    #[default]
+    Bounded,
+    /// Moving off one edge enters the grid again from the opposite edge
+    Torus,
+}
+
+impl Topology {
+    /// The location one step away in direction `d`. A bounded grid returns
+    /// `from` when the step would leave the grid.
+    pub fn next_location(self, from: Location, d: Direction) -> Location {
+        match self {
+            Topology::Bounded => from.next_location(d),
+            Topology::Torus => from.wrapping_next_location(d),
+        }
+    }
+
+    pub fn is_valid_move(self, from: Location, d: Direction) -> bool {
+        match self {
+            Topology::Bounded => from.is_valid_move(d),
+            Topology::Torus => true,
+        }
+    }
+
+    /// Column and row distance between two locations, going around the edges
+    /// on a torus when that is shorter
+    pub fn offset(self, from: Location, to: Location) -> (u16, u16) {
+        let col_diff = from.col.abs_diff(to.col);
+        let row_diff = from.row.abs_diff(to.row);
+        match self {
+            Topology::Bounded => (col_diff, row_diff),
+            Topology::Torus => (col_diff.min(COLS - col_diff), row_diff.min(ROWS - row_diff)),
+        }
+    }
+
+    /// Which way (-1, 0 or 1 for columns and rows) to go from `from` to get
+    /// to `to` the shortest way, which may wrap around the edge on a torus
+    pub fn heading(self, from: Location, to: Location) -> (i32, i32) {
+        let (col_diff, row_diff) = self.offset(from, to);
+        (
+            sign(from.col, to.col, col_diff),
+            sign(from.row, to.row, row_diff),
+        )
+    }
+}
+
+/// Direction (-1, 0 or 1) to go from `from` to `to` when they are `diff` apart
+/// along the shortest way
+fn sign(from: u16, to: u16, diff: u16) -> i32 {
+    if diff == 0 {
+        return 0;
+    }
+    let forward = to > from;
+    let wraps = diff != from.abs_diff(to);
+    if forward != wraps { 1 } else { -1 }
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Direction::Up => "up",
+            Direction::Down => "down",
+            Direction::Left => "left",
+            Direction::Right => "right",
+            Direction::UpLeft => "up-left",
+            Direction::UpRight => "up-right",
+            Direction::DownLeft => "down-left",
+            Direction::DownRight => "down-right",
+        })
+    }
+}
+
+impl FromStr for Direction {
+    type Err = String;
+
+    /// "up", "down", "left", "right", or a diagonal like "up-left"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "up" => Ok(Direction::Up),
+            "down" => Ok(Direction::Down),
+            "left" => Ok(Direction::Left),
+            "right" => Ok(Direction::Right),
+            "up-left" => Ok(Direction::UpLeft),
+            "up-right" => Ok(Direction::UpRight),
+            "down-left" => Ok(Direction::DownLeft),
+            "down-right" => Ok(Direction::DownRight),
+            _ => Err(format!("unknown direction '{s}'")),
+        }
+    }
+}
+
+impl FromStr for Topology {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bounded" => Ok(Topology::Bounded),
+            "torus" => Ok(Topology::Torus),
+            _ => Err(format!("unknown topology '{s}'")),
+        }
+    }
+}
+
+impl FromStr for Movement {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "four" | "4" => Ok(Movement::Four),
+            "eight" | "8" => Ok(Movement::Eight),
+            "octile" => Ok(Movement::Octile),
+            _ => Err(format!("unknown movement '{s}'")),
+        }
+    }
 }
 
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
@@ -19,6 +229,12 @@ impl Location {
         Location { col: c, row: r }
     }
     pub fn next_location(self, d: Direction) -> Location {
+        if let Some((vertical, horizontal)) = d.components() {
+            if !self.is_valid_move(d) {
+                return self;
+            }
+            return self.next_location(vertical).next_location(horizontal);
+        }
         match d {
             Direction::Up => {
                 if self.row > 0 {
@@ -48,6 +264,21 @@ impl Location {
                     self
                 }
             }
+            _ => unreachable!("diagonals are handled above"),
+        }
+    }
+    /// Like `next_location`, but wraps around to the opposite edge
+    pub fn wrapping_next_location(self, d: Direction) -> Location {
+        match d {
+            Direction::Up => Location::new(self.col, (self.row + ROWS - 1) % ROWS),
+            Direction::Down => Location::new(self.col, (self.row + 1) % ROWS),
+            Direction::Left => Location::new((self.col + COLS - 1) % COLS, self.row),
+            Direction::Right => Location::new((self.col + 1) % COLS, self.row),
+            _ => {
+                let (vertical, horizontal) = d.components().expect("diagonal direction");
+                self.wrapping_next_location(vertical)
+                    .wrapping_next_location(horizontal)
+            }
         }
     }
     pub fn is_valid_move(self, d: Direction) -> bool {
@@ -56,6 +287,9 @@ impl Location {
             Direction::Down => self.row < ROWS - 1,
             Direction::Left => self.col > 0,
             Direction::Right => self.col < COLS - 1,
+            _ => d
+                .components()
+                .is_some_and(|(v, h)| self.is_valid_move(v) && self.is_valid_move(h)),
         }
     }
     pub fn distance(self, other: Location) -> u16 {
@@ -186,4 +420,107 @@ mod tests {
         assert!(loc.is_valid_move(Direction::Left));
         assert!(!loc.is_valid_move(Direction::Right));
     }
+
+    #[test]
+    fn test_chebyshev_estimate() {
+        assert_eq!(Movement::Eight.estimate(3, 4), 4 * STRAIGHT_COST);
+        assert_eq!(Movement::Eight.estimate(4, 3), 4 * STRAIGHT_COST);
+    }
+
+    #[test]
+    fn test_octile_estimate() {
+        // 3 diagonal steps and 1 straight one
+        assert_eq!(
+            Movement::Octile.estimate(3, 4),
+            3 * DIAGONAL_COST + STRAIGHT_COST
+        );
+    }
+
+    #[test]
+    fn test_next_location_diagonal() {
+        let loc = Location::new(5, 5);
+        assert_eq!(loc.next_location(Direction::UpLeft), Location::new(4, 4));
+        assert_eq!(loc.next_location(Direction::UpRight), Location::new(6, 4));
+        assert_eq!(loc.next_location(Direction::DownLeft), Location::new(4, 6));
+        assert_eq!(loc.next_location(Direction::DownRight), Location::new(6, 6));
+    }
+
+    #[test]
+    fn test_diagonal_boundary() {
+        let loc = Location::new(0, 5);
+        assert!(!loc.is_valid_move(Direction::UpLeft));
+        assert!(loc.is_valid_move(Direction::UpRight));
+        // a diagonal that leaves the grid does not slide along the edge
+        assert_eq!(loc.next_location(Direction::DownLeft), loc);
+    }
+
+    #[test]
+    fn test_movement_estimate() {
+        assert_eq!(Movement::Four.estimate(2, 2), 40);
+        assert_eq!(Movement::Eight.estimate(2, 2), 20);
+        assert_eq!(Movement::Octile.estimate(2, 2), 28);
+        assert_eq!(Movement::Four.directions().len(), 4);
+        assert_eq!(Movement::Octile.directions().len(), 8);
+    }
+
+    #[test]
+    fn test_parse_movement() {
+        assert_eq!("four".parse(), Ok(Movement::Four));
+        assert_eq!("8".parse(), Ok(Movement::Eight));
+        assert_eq!("octile".parse(), Ok(Movement::Octile));
+        assert!("hex".parse::<Movement>().is_err());
+    }
+
+    #[test]
+    fn test_wrapping_next_location() {
+        let corner = Location::new(0, 0);
+        assert_eq!(
+            corner.wrapping_next_location(Direction::Up),
+            Location::new(0, ROWS - 1)
+        );
+        assert_eq!(
+            corner.wrapping_next_location(Direction::Left),
+            Location::new(COLS - 1, 0)
+        );
+        assert_eq!(
+            corner.wrapping_next_location(Direction::UpLeft),
+            Location::new(COLS - 1, ROWS - 1)
+        );
+        let far = Location::new(COLS - 1, ROWS - 1);
+        assert_eq!(far.wrapping_next_location(Direction::DownRight), corner);
+        let loc = Location::new(5, 5);
+        assert_eq!(
+            loc.wrapping_next_location(Direction::Up),
+            loc.next_location(Direction::Up)
+        );
+    }
+
+    #[test]
+    fn test_torus_offset() {
+        let a = Location::new(0, 2);
+        let b = Location::new(COLS - 1, 2);
+        assert_eq!(Topology::Bounded.offset(a, b), (COLS - 1, 0));
+        assert_eq!(Topology::Torus.offset(a, b), (1, 0));
+        assert!(Topology::Torus.is_valid_move(a, Direction::Left));
+        assert!(!Topology::Bounded.is_valid_move(a, Direction::Left));
+    }
+
+    #[test]
+    fn test_parse_direction() {
+        assert_eq!("up".parse(), Ok(Direction::Up));
+        assert_eq!("down-left".parse(), Ok(Direction::DownLeft));
+        assert!("north".parse::<Direction>().is_err());
+        for d in ALL_DIRECTIONS {
+            assert_eq!(d.to_string().parse(), Ok(d));
+        }
+    }
+
+    #[test]
+    fn test_heading() {
+        let a = Location::new(0, 2);
+        let b = Location::new(COLS - 1, 5);
+        assert_eq!(Topology::Bounded.heading(a, b), (1, 1));
+        assert_eq!(Topology::Torus.heading(a, b), (-1, 1));
+        assert_eq!(Topology::Torus.heading(a, a), (0, 0));
+    }
 }
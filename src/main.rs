@@ -1,40 +1,67 @@
 use glutin_window::GlutinWindow;
-use grid::Grid;
-use objects::{AgentInfo, GO};
 use opengl_graphics::{Filter, GlGraphics, GlyphCache, OpenGL, TextureSettings};
 use piston::{
-    CloseEvent, EventSettings, Events, OpenGLWindow, RenderEvent, UpdateEvent, WindowSettings,
+    Button, CloseEvent, EventLoop, EventSettings, Events, Key, MouseButton, MouseCursorEvent,
+    MouseScrollEvent, OpenGLWindow, PressEvent, ReleaseEvent, RenderEvent, ResizeEvent,
+    UpdateEvent, WindowSettings,
 };
 use rusttype::Font;
-use std::process::exit;
-
-mod astar;
-mod draw;
-mod grid;
-mod location;
-mod objects;
+use std::{
+    process::exit,
+    time::{Duration, Instant},
+};
+use tileworld::{
+    FONT,
+    clock::Clock,
+    config::Config,
+    draw::{self, Overlay},
+    editor::Editor,
+    env::Env,
+    external::ExternalAgents,
+    heatmap::Layer,
+    human::Human,
+    inspector::Inspector,
+    objects::{Action, GO},
+    qlearning::{self, QAgent, QParams},
+    raster::Recorder,
+    server::Server,
+    tui::{self, Command, Terminal},
+    view::{View, WINDOW_SIZE},
+};
 
-const COLS: u16 = 40;
-const ROWS: u16 = 40;
-const MAG: f64 = 20.0;
 const UPDATES_PER_SECOND: u64 = 7; // ~143ms between updates (similar to old 150ms delay)
 
 fn main() {
+    let config = Config::from_args().unwrap_or_else(|e| {
+        eprintln!("{e}");
+        exit(2);
+    });
+    if config.train.is_some() || config.evaluate.is_some() {
+        run_headless(&config);
+        exit(0);
+    }
+    if let Some(ticks) = config.headless {
+        run_ticks(&config, ticks);
+        exit(0);
+    }
+    if config.tui {
+        run_terminal(&config);
+        exit(0);
+    }
+    let mut controllers = Controllers::start(&config);
+
     #[cfg(target_os = "macos")]
     let opengl = OpenGL::V3_2;
     #[cfg(target_os = "linux")]
     let opengl = OpenGL::V2_1;
 
-    let settings = WindowSettings::new(
-        "TileWorld",
-        (f64::from(COLS) * MAG + 200.0, f64::from(ROWS) * MAG),
-    )
-    .automatic_close(true)
-    .graphics_api(opengl)
-    .vsync(true);
+    let settings = WindowSettings::new("TileWorld", WINDOW_SIZE)
+        .automatic_close(true)
+        .resizable(true)
+        .graphics_api(opengl)
+        .vsync(true);
     let texture_settings = TextureSettings::new().filter(Filter::Nearest);
-    let font_data: &[u8] = include_bytes!("../UbuntuMono-R.ttf");
-    let font: Font<'static> = Font::try_from_bytes(font_data).expect("failed to load font");
+    let font: Font<'static> = Font::try_from_bytes(FONT).expect("failed to load font");
     let glyphs = &mut GlyphCache::from_font(font, (), texture_settings);
     let mut window: GlutinWindow = settings.build().expect("Could not create window");
 
@@ -43,44 +70,140 @@ fn main() {
     event_settings.ups = UPDATES_PER_SECOND; // Game logic updates per second
     event_settings.max_fps = 60; // Render up to 60 fps for smooth visuals
     let mut events = Events::new(event_settings);
+    let mut clock = Clock::new(UPDATES_PER_SECOND);
+    // edits the grid while paused
+    let mut editor = Editor::new();
+    // describes the clicked cell or agent
+    let mut inspector = Inspector::new();
+    let mut cursor = [0.0; 2];
+    // how large the grid is drawn and which part of it is shown
+    let mut view = View::new(WINDOW_SIZE);
 
     gl::load_with(|s| window.get_proc_address(s) as *const _);
     let mut gl = GlGraphics::new(opengl);
-    let mut g = Grid::new();
-    let (agents, tiles, holes) = g.create_objects(6, 20, 20, 20);
-
-    // Cache agent info for rendering (updated on game update, used on render)
-    let mut agent_info: Vec<AgentInfo> = agents
-        .iter()
-        .filter_map(|go| {
-            if let GO::Agent(ref a) = *go.borrow() {
-                Some(AgentInfo::from(a))
-            } else {
-                None
-            }
-        })
-        .collect();
+    let mut env = Env::new(config.clone());
+    let mut recorder = start_recorder(&config);
+    let mut human = config.play.then(|| Human::new(env.agents()[0].clone()));
+
+    // Agent whose belief is drawn over the grid, toggled with B
+    let mut belief_view: Option<u8> = None;
+    // paths and searches drawn over the grid, cycled with V
+    let mut overlay = Overlay::default();
+    // where the agents went or got stuck, cycled with M
+    let mut heatmap = Layer::default();
 
     while let Some(e) = events.next(&mut window) {
         if e.close_args().is_some() {
             break;
         }
 
-        // Handle game logic updates (at UPDATES_PER_SECOND rate)
-        if e.update_args().is_some() {
-            g.update(&agents, &tiles, &holes);
-
-            // Update cached agent info for rendering
-            agent_info = agents
-                .iter()
-                .filter_map(|go| {
-                    if let GO::Agent(ref a) = *go.borrow() {
-                        Some(AgentInfo::from(a))
+        if let Some(Button::Keyboard(key)) = e.press_args()
+            && let Some(ref mut human) = human
+            && human.press(key)
+        {
+            continue;
+        }
+        if let Some(Button::Keyboard(key)) = e.release_args()
+            && let Some(ref mut human) = human
+        {
+            human.release(key);
+        }
+
+        if let Some(Button::Keyboard(key)) = e.press_args()
+            && clock.press(key)
+        {
+            events.set_ups(clock.ups());
+            continue;
+        }
+
+        if let Some(args) = e.resize_args() {
+            view.resize(args.window_size);
+        }
+        if let Some([_, scroll]) = e.mouse_scroll_args() {
+            view.zoom_at(cursor, scroll);
+        }
+        if let Some(position) = e.mouse_cursor_args() {
+            cursor = position;
+            if !view.drag_to(cursor)
+                && clock.paused()
+                && let Some(l) = view.cell_at(cursor)
+            {
+                editor.drag(&mut env, l);
+            }
+        }
+        match e.press_args() {
+            Some(Button::Mouse(button)) => {
+                let editing = clock.paused() && !editor.inspecting();
+                // the middle button pans, and so does the right one unless it clears cells
+                if button == MouseButton::Middle || (button == MouseButton::Right && !editing) {
+                    view.start_drag(cursor);
+                } else if let Some(l) = view.cell_at(cursor) {
+                    if !editing {
+                        if button == MouseButton::Left {
+                            inspector.select(&env, l);
+                        }
                     } else {
-                        None
+                        editor.click(&mut env, l, button);
                     }
-                })
-                .collect();
+                }
+            }
+            Some(Button::Keyboard(Key::Escape)) => inspector.clear(),
+            Some(Button::Keyboard(Key::F)) => view.fit(),
+            Some(Button::Keyboard(Key::S)) if clock.paused() => {
+                match env.map().save(&config.map_file) {
+                    Ok(()) => println!("saved the map to {}", config.map_file.display()),
+                    Err(e) => eprintln!("cannot save {}: {e}", config.map_file.display()),
+                }
+            }
+            Some(Button::Keyboard(key)) if clock.paused() && editor.press(key) => continue,
+            _ => {}
+        }
+        if let Some(Button::Mouse(_)) = e.release_args() {
+            view.end_drag();
+            editor.release();
+        }
+
+        if let Some(Button::Keyboard(Key::V)) = e.press_args() {
+            overlay = overlay.next();
+        }
+
+        if let Some(Button::Keyboard(Key::M)) = e.press_args() {
+            heatmap = heatmap.next();
+        }
+
+        if let Some(Button::Keyboard(Key::B)) = e.press_args() {
+            // cycle through the agents, then back to the real grid
+            let mut ids = env.agent_info().iter().map(|a| a.id);
+            belief_view = match belief_view {
+                None => ids.next(),
+                Some(id) => ids.find(|&next| next > id),
+            };
+        }
+
+        // Handle game logic updates (at UPDATES_PER_SECOND rate)
+        if e.update_args().is_some() && env.episode().ended().is_none() && clock.tick() {
+            let mut actions = controllers.actions(&env);
+            // the player's agent may have been taken off the grid in the editor
+            if let Some(ref mut human) = human
+                && let Some(slot) = human.slot(&env).and_then(|i| actions.get_mut(i))
+            {
+                *slot = Some(human.action());
+            }
+            let (_, _, done, _) = env.step_agents(&actions);
+            record(&mut recorder, &env, &clock);
+            if done {
+                println!("{}", env.summary());
+                if config.episode.auto_reset {
+                    env.reset(None);
+                    // the next episode has new agents, the player takes agent 1 again
+                    if let Some(ref mut human) = human
+                        && let Some(agent) = env.agents().first()
+                    {
+                        human.agent = agent.clone();
+                    }
+                }
+            }
+            controllers.publish(&env);
         }
 
         // Handle rendering (at up to max_fps rate)
@@ -88,9 +211,238 @@ fn main() {
             gl.draw(args.viewport(), |ctx, glgraphics| {
                 use graphics::clear;
                 clear([1.0; 4], glgraphics);
-                draw::draw(&g, &agent_info, glyphs, &ctx, glgraphics);
+                draw::draw(&env, &clock, &view, glyphs, &ctx, glgraphics);
+                draw::draw_heatmap(&env, heatmap, &view, glyphs, &ctx, glgraphics);
+                draw::draw_paths(&env, overlay, &view, &ctx, glgraphics);
+                if let Some(id) = belief_view
+                    && let Some(agent) = env.agent_slot(id).map(|i| &env.agents()[i])
+                    && let GO::Agent(ref a) = *agent.borrow()
+                {
+                    draw::draw_belief(a, env.grid().tick(), &view, glyphs, &ctx, glgraphics);
+                }
+                draw::draw_inspector(&inspector, &env, &view, glyphs, &ctx, glgraphics);
+                if clock.paused() {
+                    draw::draw_editor(&editor, &view, glyphs, &ctx, glgraphics);
+                }
+                if let Some(ref human) = human
+                    && let Some(agent) = human.slot(&env).map(|i| &env.agent_info()[i])
+                {
+                    draw::draw_player(agent, &view, glyphs, &ctx, glgraphics);
+                }
             });
         }
     }
+    save_heatmap(&config, &env);
+    // exit doesn't run destructors, stop the external agents first
+    drop(controllers);
     exit(0);
 }
+
+/// What chooses actions for agents instead of their own planning: the
+/// Q-learning agent, external processes and remote clients
+struct Controllers {
+    q_agent: Option<QAgent>,
+    external: ExternalAgents,
+    server: Option<Server>,
+}
+
+impl Controllers {
+    fn start(config: &Config) -> Self {
+        let timeout = Duration::from_millis(config.external_timeout);
+        // external agents come after the Q-learning agent or the player, remote ones play the rest
+        let external = ExternalAgents::spawn(&config.external, config.first_external(), timeout)
+            .unwrap_or_else(|e| {
+                eprintln!("cannot start external agent: {e}");
+                exit(1);
+            });
+        let server = config.serve.as_ref().map(|addr| {
+            Server::bind(addr, timeout, config.free_agents()).unwrap_or_else(|e| {
+                eprintln!("cannot listen on {addr}: {e}");
+                exit(1);
+            })
+        });
+        Controllers {
+            q_agent: config.q_agent.then(|| load_q_agent(config)),
+            external,
+            server,
+        }
+    }
+
+    /// An action or None for each agent, for the next tick
+    fn actions(&mut self, env: &Env) -> Vec<Option<Action>> {
+        let mut actions = self.external.actions(env);
+        if let Some(ref mut server) = self.server {
+            server.accept(env);
+            for (action, remote) in actions.iter_mut().zip(server.actions(env)) {
+                *action = action.or(remote);
+            }
+        }
+        // agent 1 comes first, unless it was taken off the grid in the editor
+        if let Some(ref mut q) = self.q_agent
+            && env.agent_slot(1) == Some(0)
+            && let Some(&action) = q.actions(env, 1).first()
+        {
+            actions[0] = Some(action);
+        }
+        actions
+    }
+
+    /// Tell the spectators what changed in the tick
+    fn publish(&mut self, env: &Env) {
+        if let Some(ref mut server) = self.server {
+            server.publish(env);
+        }
+    }
+}
+
+/// Run the simulation without a window, printing how each episode went
+fn run_ticks(config: &Config, ticks: u64) {
+    let mut controllers = Controllers::start(config);
+    let mut env = Env::new(config.clone());
+    let mut recorder = start_recorder(config);
+    // only shown in the sidebar of recorded frames
+    let clock = Clock::new(UPDATES_PER_SECOND);
+    for _ in 0..ticks {
+        let actions = controllers.actions(&env);
+        let (_, _, done, _) = env.step_agents(&actions);
+        record(&mut recorder, &env, &clock);
+        if done {
+            println!("{}", env.summary());
+            env.reset(None);
+        }
+        controllers.publish(&env);
+    }
+    save_heatmap(config, &env);
+}
+
+/// Show the simulation in the terminal until Q is pressed
+fn run_terminal(config: &Config) {
+    let mut controllers = Controllers::start(config);
+    let mut env = Env::new(config.clone());
+    let mut recorder = start_recorder(config);
+    let mut clock = Clock::new(UPDATES_PER_SECOND);
+    let mut terminal = Terminal::start().unwrap_or_else(|e| {
+        eprintln!("cannot use the terminal: {e}");
+        exit(1);
+    });
+    let mut due = Instant::now();
+    // episode summaries wait until the terminal is back to normal
+    let mut summaries = vec![];
+    let result = loop {
+        if Instant::now() >= due {
+            due = Instant::now() + Duration::from_secs(1) / clock.ups() as u32;
+            if env.episode().ended().is_none() && clock.tick() {
+                let actions = controllers.actions(&env);
+                let (_, _, done, _) = env.step_agents(&actions);
+                record(&mut recorder, &env, &clock);
+                if done {
+                    summaries.push(env.summary());
+                    if config.episode.auto_reset {
+                        env.reset(None);
+                    }
+                }
+                controllers.publish(&env);
+            }
+        } else {
+            match terminal.poll(due) {
+                Ok(Some(Command::Quit)) => break Ok(()),
+                Ok(Some(Command::Clock(key))) => {
+                    clock.press(key);
+                }
+                Ok(None) => continue,
+                Err(e) => break Err(e),
+            }
+        }
+        if let Err(e) = terminal.draw(&tui::lines(&env, &clock)) {
+            break Err(e);
+        }
+    };
+    drop(terminal);
+    for summary in summaries {
+        println!("{summary}");
+    }
+    if let Err(e) = result {
+        eprintln!("terminal error: {e}");
+    }
+    save_heatmap(config, &env);
+}
+
+/// A recorder when --frames or --gif asks for one
+fn start_recorder(config: &Config) -> Option<Recorder> {
+    if config.frames.is_none() && config.gif.is_none() {
+        return None;
+    }
+    let recorder = Recorder::new(
+        config.frames.as_deref(),
+        config.gif.as_deref(),
+        config.record_every,
+    );
+    Some(recorder.unwrap_or_else(|e| {
+        eprintln!("cannot record the run: {e}");
+        exit(1);
+    }))
+}
+
+/// Save a frame when one is due, giving up on recording when that fails
+fn record(recorder: &mut Option<Recorder>, env: &Env, clock: &Clock) {
+    if let Some(r) = recorder
+        && let Err(e) = r.record(env, clock)
+    {
+        eprintln!("stopped recording: {e}");
+        *recorder = None;
+    }
+}
+
+/// Save the heatmap when --heatmap asks for it, over the obstacles of the
+/// last episode
+fn save_heatmap(config: &Config, env: &Env) {
+    if let Some(ref path) = config.heatmap {
+        match env.heatmap().save_png(env.grid(), path) {
+            Ok(()) => println!("saved the heatmap to {}", path.display()),
+            Err(e) => eprintln!("cannot save {}: {e}", path.display()),
+        }
+    }
+}
+
+/// Train and/or evaluate the Q-learning agent, printing how it does
+fn run_headless(config: &Config) {
+    if let Some(episodes) = config.train {
+        let mut agent = if config.q_table.exists() {
+            load_q_agent(config)
+        } else {
+            QAgent::new(QParams::default(), config.seed.unwrap_or_else(rand::random))
+        };
+        let mut trained = 0;
+        while trained < episodes {
+            let batch = (episodes - trained).min(100);
+            let scores = qlearning::train(config, &mut agent, batch);
+            trained += batch;
+            let average = scores.iter().sum::<u32>() as f32 / batch as f32;
+            println!("episode {trained}: average score {average:.1}");
+        }
+        agent.save(&config.q_table).unwrap_or_else(|e| {
+            eprintln!("cannot save {}: {e}", config.q_table.display());
+            exit(1);
+        });
+    }
+    if let Some(episodes) = config.evaluate {
+        let mut agent = load_q_agent(config);
+        let seed = config.seed.unwrap_or_else(rand::random);
+        let (learned, greedy) = qlearning::evaluate(config, &mut agent, episodes, seed);
+        println!(
+            "average score of agent 1 over {episodes} episodes: Q-learning {learned:.1}, built-in {greedy:.1}"
+        );
+    }
+}
+
+fn load_q_agent(config: &Config) -> QAgent {
+    QAgent::load(
+        &config.q_table,
+        QParams::default(),
+        config.seed.unwrap_or_else(rand::random),
+    )
+    .unwrap_or_else(|e| {
+        eprintln!("cannot load {}: {e}", config.q_table.display());
+        exit(1);
+    })
+}
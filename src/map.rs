@@ -0,0 +1,169 @@
+use crate::{
+    COLS, ROWS,
+    grid::Grid,
+    location::{Location, Topology},
+    objects::{GO, ObstacleKind, ObstacleState},
+};
+use std::{collections::HashMap, fmt, fs, io, path::Path, str::FromStr};
+
+/// Something that can be put on a cell of a map
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Item {
+    Obstacle,
+    Tile(u32),
+    Hole,
+    /// Agents get their ids in the order they appear, row by row
+    Agent,
+}
+
+/// A starting layout for the grid, saved as text with one line per row and
+/// one character per cell: `.` for an empty cell, `#` for an obstacle, `o`
+/// for a hole, `A` for an agent, and a digit from 1 to 9 for a tile with that
+/// score
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Map {
+    items: HashMap<Location, Item>,
+}
+
+impl Map {
+    pub fn new() -> Self {
+        Map::default()
+    }
+
+    /// Put `item` on the cell at `l`, replacing whatever was there
+    pub fn set(&mut self, l: Location, item: Item) {
+        self.items.insert(l, item);
+    }
+
+    pub fn get(&self, l: Location) -> Option<Item> {
+        self.items.get(&l).copied()
+    }
+
+    /// Everything on the map, row by row
+    pub fn items(&self) -> Vec<(Location, Item)> {
+        let mut items: Vec<(Location, Item)> =
+            self.items.iter().map(|(&l, &item)| (l, item)).collect();
+        items.sort_by_key(|&(l, _)| (l.row, l.col));
+        items
+    }
+
+    pub fn count(&self, item: Item) -> usize {
+        self.items.values().filter(|&&i| i == item).count()
+    }
+
+    /// Whether every cell without an obstacle can be reached from every
+    /// other one, as on a grid with `topology`
+    pub fn is_connected(&self, topology: Topology) -> bool {
+        let mut grid = Grid::new();
+        grid.set_topology(topology);
+        for (l, item) in self.items() {
+            if item == Item::Obstacle {
+                grid.place(GO::Obstacle(ObstacleState::new(l, ObstacleKind::Static)));
+            }
+        }
+        grid.is_connected()
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        fs::read_to_string(path)?
+            .parse()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, self.to_string())
+    }
+}
+
+impl FromStr for Map {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lines: Vec<&str> = s.lines().collect();
+        if lines.len() != usize::from(ROWS) {
+            return Err(format!("a map has {ROWS} lines, found {}", lines.len()));
+        }
+        let mut map = Map::new();
+        for (row, line) in (0..).zip(lines) {
+            if line.chars().count() != usize::from(COLS) {
+                return Err(format!("line {}: a map has {COLS} cells per line", row + 1));
+            }
+            for (col, c) in (0..).zip(line.chars()) {
+                let item = match c {
+                    '.' => continue,
+                    '#' => Item::Obstacle,
+                    'o' => Item::Hole,
+                    'A' => Item::Agent,
+                    '1'..='9' => Item::Tile(c.to_digit(10).unwrap_or_default()),
+                    _ => return Err(format!("line {}: unknown cell '{c}'", row + 1)),
+                };
+                map.set(Location::new(col, row), item);
+            }
+        }
+        Ok(map)
+    }
+}
+
+impl fmt::Display for Map {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for row in 0..ROWS {
+            for col in 0..COLS {
+                let c = match self.get(Location::new(col, row)) {
+                    None => '.',
+                    Some(Item::Obstacle) => '#',
+                    Some(Item::Hole) => 'o',
+                    Some(Item::Agent) => 'A',
+                    Some(Item::Tile(score)) => {
+                        char::from_digit(score.clamp(1, 9), 10).unwrap_or('1')
+                    }
+                };
+                write!(f, "{c}")?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_map_roundtrip() {
+        let mut map = Map::new();
+        map.set(Location::new(0, 0), Item::Obstacle);
+        map.set(Location::new(5, 2), Item::Tile(3));
+        map.set(Location::new(COLS - 1, ROWS - 1), Item::Hole);
+        map.set(Location::new(7, 2), Item::Agent);
+        let text = map.to_string();
+        assert_eq!(text.lines().count(), usize::from(ROWS));
+        assert!(text.starts_with("#....."));
+        assert_eq!(text.parse::<Map>(), Ok(map.clone()));
+        assert_eq!(map.items()[1], (Location::new(5, 2), Item::Tile(3)));
+        assert_eq!(map.count(Item::Agent), 1);
+    }
+
+    #[test]
+    fn test_walled_off_corner() {
+        let mut map = Map::new();
+        map.set(Location::new(1, 0), Item::Obstacle);
+        assert!(map.is_connected(Topology::Bounded));
+        map.set(Location::new(0, 1), Item::Obstacle);
+        assert!(!map.is_connected(Topology::Bounded));
+        // on a torus the corner can still be left across the edges
+        assert!(map.is_connected(Topology::Torus));
+    }
+
+    #[test]
+    fn test_invalid_maps() {
+        assert!("#.o".parse::<Map>().is_err());
+        let mut text = Map::new().to_string();
+        text.replace_range(0..1, "x");
+        assert!(
+            text.parse::<Map>()
+                .unwrap_err()
+                .contains("unknown cell 'x'")
+        );
+    }
+}
@@ -0,0 +1,274 @@
+use crate::{
+    COLS, ROWS,
+    location::{DIRECTIONS, Location},
+};
+use rand::{Rng, seq::SliceRandom};
+use std::{
+    collections::{HashSet, VecDeque},
+    str::FromStr,
+};
+
+/// Obstacle layouts the grid can start out with
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Generator {
+    /// No layout, only the randomly scattered obstacles
+    #[default]
+    Random,
+    /// A perfect maze: exactly one route between any two open cells
+    Maze,
+    /// Rectangular rooms joined by one cell wide corridors
+    Rooms,
+    /// Rows of two cell deep shelving separated by aisles
+    Warehouse,
+    /// Organic caves grown by a cellular automaton
+    Caves,
+}
+
+impl FromStr for Generator {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "random" => Ok(Generator::Random),
+            "maze" => Ok(Generator::Maze),
+            "rooms" => Ok(Generator::Rooms),
+            "warehouse" => Ok(Generator::Warehouse),
+            "caves" => Ok(Generator::Caves),
+            _ => Err(format!("unknown generator '{s}'")),
+        }
+    }
+}
+
+impl Generator {
+    /// The cells that should hold an obstacle. All other cells are connected
+    /// to each other, even when only moving up, down, left and right.
+    pub fn generate<R: Rng>(self, rng: &mut R) -> HashSet<Location> {
+        let mut walls = match self {
+            Generator::Random => HashSet::new(),
+            Generator::Maze => maze(rng),
+            Generator::Rooms => rooms(rng),
+            Generator::Warehouse => warehouse(),
+            Generator::Caves => caves(rng),
+        };
+        fill_pockets(&mut walls);
+        walls
+    }
+}
+
+fn all_cells() -> impl Iterator<Item = Location> {
+    (0..ROWS).flat_map(|r| (0..COLS).map(move |c| Location::new(c, r)))
+}
+
+/// The neighbours of `l` in the four straight directions that are on the grid
+fn neighbours(l: Location) -> impl Iterator<Item = Location> {
+    DIRECTIONS
+        .into_iter()
+        .filter(move |&d| l.is_valid_move(d))
+        .map(move |d| l.next_location(d))
+}
+
+/// Recursive backtracker over the cells with even coordinates; the cells in
+/// between are walls until a passage is carved through them.
+fn maze<R: Rng>(rng: &mut R) -> HashSet<Location> {
+    let mut walls: HashSet<Location> = all_cells().collect();
+    let start = Location::new(0, 0);
+    walls.remove(&start);
+    let mut stack = vec![start];
+    while let Some(&current) = stack.last() {
+        let mut next: Vec<(Location, Location)> = DIRECTIONS
+            .into_iter()
+            .filter(|&d| {
+                let between = current.next_location(d);
+                between != current && between.is_valid_move(d)
+            })
+            .map(|d| {
+                let between = current.next_location(d);
+                (between, between.next_location(d))
+            })
+            .filter(|(_, cell)| walls.contains(cell))
+            .collect();
+        next.shuffle(rng);
+        if let Some(&(between, cell)) = next.first() {
+            walls.remove(&between);
+            walls.remove(&cell);
+            stack.push(cell);
+        } else {
+            stack.pop();
+        }
+    }
+    walls
+}
+
+/// Random non-overlapping rooms, each joined to the previous one by an
+/// L-shaped corridor
+fn rooms<R: Rng>(rng: &mut R) -> HashSet<Location> {
+    let mut walls: HashSet<Location> = all_cells().collect();
+    let mut placed: Vec<(u16, u16, u16, u16)> = vec![];
+    for _ in 0..100 {
+        let w = rng.random_range(4..10);
+        let h = rng.random_range(4..10);
+        let c = rng.random_range(1..COLS - w);
+        let r = rng.random_range(1..ROWS - h);
+        // keep at least one wall between rooms
+        let overlaps = placed
+            .iter()
+            .any(|&(pc, pr, pw, ph)| c <= pc + pw && pc <= c + w && r <= pr + ph && pr <= r + h);
+        if overlaps {
+            continue;
+        }
+        for row in r..r + h {
+            for col in c..c + w {
+                walls.remove(&Location::new(col, row));
+            }
+        }
+        if let Some(&(pc, pr, pw, ph)) = placed.last() {
+            let from = Location::new(pc + pw / 2, pr + ph / 2);
+            let to = Location::new(c + w / 2, r + h / 2);
+            for col in from.col.min(to.col)..=from.col.max(to.col) {
+                walls.remove(&Location::new(col, from.row));
+            }
+            for row in from.row.min(to.row)..=from.row.max(to.row) {
+                walls.remove(&Location::new(to.col, row));
+            }
+        }
+        placed.push((c, r, w, h));
+    }
+    walls
+}
+
+/// Shelving two cells deep, running left to right, with an aisle between
+/// each pair of shelves and a cross aisle every ten columns
+fn warehouse() -> HashSet<Location> {
+    all_cells()
+        .filter(|l| {
+            let in_margin = l.col < 2 || l.col >= COLS - 2 || l.row < 2 || l.row >= ROWS - 2;
+            let cross_aisle = l.col % 10 < 2;
+            !in_margin && !cross_aisle && (l.row - 2) % 4 < 2
+        })
+        .collect()
+}
+
+/// Start from noise and repeatedly turn each cell into a wall when most of
+/// its neighbours are walls
+fn caves<R: Rng>(rng: &mut R) -> HashSet<Location> {
+    let mut walls: HashSet<Location> = all_cells().filter(|_| rng.random_bool(0.45)).collect();
+    for _ in 0..5 {
+        walls = all_cells()
+            .filter(|l| {
+                let mut count = 0;
+                for dr in -1i32..=1 {
+                    for dc in -1i32..=1 {
+                        let c = i32::from(l.col) + dc;
+                        let r = i32::from(l.row) + dr;
+                        let outside =
+                            c < 0 || r < 0 || c >= i32::from(COLS) || r >= i32::from(ROWS);
+                        // the cell itself counts too, the edge of the grid counts as wall
+                        if outside || walls.contains(&Location::new(c as u16, r as u16)) {
+                            count += 1;
+                        }
+                    }
+                }
+                count >= 5
+            })
+            .collect();
+    }
+    walls
+}
+
+/// Turn every open cell outside the largest open region into a wall
+fn fill_pockets(walls: &mut HashSet<Location>) {
+    let mut unvisited: HashSet<Location> = all_cells().filter(|l| !walls.contains(l)).collect();
+    let mut largest = HashSet::new();
+    while let Some(&start) = unvisited.iter().next() {
+        let mut region = HashSet::from([start]);
+        let mut queue = VecDeque::from([start]);
+        unvisited.remove(&start);
+        while let Some(current) = queue.pop_front() {
+            for next in neighbours(current) {
+                if unvisited.remove(&next) {
+                    region.insert(next);
+                    queue.push_back(next);
+                }
+            }
+        }
+        if region.len() > largest.len() {
+            largest = region;
+        }
+    }
+    walls.extend(all_cells().filter(|l| !largest.contains(l)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GENERATORS: [Generator; 5] = [
+        Generator::Random,
+        Generator::Maze,
+        Generator::Rooms,
+        Generator::Warehouse,
+        Generator::Caves,
+    ];
+
+    /// Number of open cells reachable from the first open cell
+    fn reachable(walls: &HashSet<Location>) -> usize {
+        let start = all_cells().find(|l| !walls.contains(l)).unwrap();
+        let mut seen = HashSet::from([start]);
+        let mut queue = VecDeque::from([start]);
+        while let Some(current) = queue.pop_front() {
+            for next in neighbours(current) {
+                if !walls.contains(&next) && seen.insert(next) {
+                    queue.push_back(next);
+                }
+            }
+        }
+        seen.len()
+    }
+
+    #[test]
+    fn test_layouts_are_connected() {
+        let mut rng = rand::rng();
+        for generator in GENERATORS {
+            let walls = generator.generate(&mut rng);
+            let open = usize::from(COLS) * usize::from(ROWS) - walls.len();
+            assert!(open > 100, "{generator:?} leaves too little room");
+            assert_eq!(reachable(&walls), open, "{generator:?} is not connected");
+        }
+    }
+
+    #[test]
+    fn test_random_has_no_layout() {
+        assert!(Generator::Random.generate(&mut rand::rng()).is_empty());
+    }
+
+    #[test]
+    fn test_maze_is_perfect() {
+        let walls = Generator::Maze.generate(&mut rand::rng());
+        let open: Vec<Location> = all_cells().filter(|l| !walls.contains(l)).collect();
+        // a tree over the open cells has exactly one edge less than it has cells
+        let edges: usize = open
+            .iter()
+            .map(|&l| neighbours(l).filter(|n| !walls.contains(n)).count())
+            .sum::<usize>()
+            / 2;
+        assert_eq!(edges, open.len() - 1);
+    }
+
+    #[test]
+    fn test_warehouse_has_aisles() {
+        let walls = Generator::Warehouse.generate(&mut rand::rng());
+        assert!(!walls.is_empty());
+        // the outer margin and the cross aisles are kept clear
+        for r in 0..ROWS {
+            assert!(!walls.contains(&Location::new(0, r)));
+            assert!(!walls.contains(&Location::new(10, r)));
+        }
+    }
+
+    #[test]
+    fn test_parse_generator() {
+        assert_eq!("maze".parse(), Ok(Generator::Maze));
+        assert_eq!("caves".parse(), Ok(Generator::Caves));
+        assert!("dungeon".parse::<Generator>().is_err());
+    }
+}
@@ -1,6 +1,12 @@
-use crate::{grid::Grid, location::{Direction, Location}};
+use crate::{
+    belief::{BeliefMap, Seen},
+    comms::{CLAIM_TIMEOUT, Claim, Content, Message, Recipient, claimed_by_other},
+    grid::Grid,
+    location::{DIRECTIONS, Direction, Location, STRAIGHT_COST},
+};
 use log::debug;
 use paste::paste;
+use rand::Rng;
 use std::{cell::RefCell, rc::Rc};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -8,12 +14,30 @@ pub enum State {
     Idle,
     MoveToTile,
     MoveToHole,
+    /// Looking for something to do, nothing useful is in sight
+    Explore,
+}
+
+/// What an agent does in a tick when it is controlled from outside instead of
+/// by its own planner
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Stay,
+    Move(Direction),
+    /// Pick up the tile the agent is standing on
+    Pick,
+    /// Drop the carried tile into the hole the agent is standing on
+    Drop,
 }
 
 #[derive(Debug, PartialEq, Eq)]
 pub struct AgentState {
     pub location: Location,
     pub id: u8,
+    /// Agents on the same team share their score and talk to each other
+    pub team: u8,
+    /// Who the agent tells about the tiles and holes it sees
+    pub sightings_to: Recipient,
     pub score: u32,
     pub tile: Option<Object>,
     pub hole: Option<Object>,
@@ -23,30 +47,66 @@ pub struct AgentState {
     cached_path: Vec<Direction>,
     // Location of target when path was calculated (to detect if target moved)
     cached_target_loc: Option<Location>,
+    // Time left before the agent can leave the cell it is on (slow terrain or
+    // diagonal steps), in tenths of a tick
+    wait: u32,
+    // Where the agent is heading while exploring
+    waypoint: Option<Location>,
+    // What the agent remembers of the grid, only kept up to date when the grid has memory enabled
+    pub belief: BeliefMap,
+    // Tiles and holes other agents said they are heading for
+    claims: Vec<Claim>,
+    // What the agent already told the others about
+    reported: Vec<Sighting>,
+    // Messages that didn't fit in the bandwidth, sent first next tick
+    outbox: Vec<Content>,
+    pub stats: AgentStats,
+    // Cells expanded by the last path search, for showing how it went
+    expanded: Vec<Location>,
+}
+
+/// What an agent did so far
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct AgentStats {
+    /// Cells moved
+    pub steps: u32,
+    pub picked: u32,
+    pub dropped: u32,
+    /// Paths planned
+    pub plans: u32,
+    /// Ticks spent waiting out slow terrain and diagonal steps
+    pub waited: u32,
+    /// Moves that something was in the way of
+    pub blocked: u32,
 }
 
 pub struct AgentInfo {
     pub id: u8,
+    pub team: u8,
     pub score: u32,
+    pub location: Location,
+    pub has_tile: bool,
 }
 
 impl From<&AgentState> for AgentInfo {
     fn from(value: &AgentState) -> Self {
         AgentInfo {
             id: value.id,
+            team: value.team,
             score: value.score,
+            location: value.location,
+            has_tile: value.has_tile,
         }
     }
 }
 
-fn get_closest(collection: &[Object], loc: Location) -> Option<Object> {
-    let mut closest: Option<Object> = None;
-    let mut dist = u16::MAX;
-    for tile_ref in collection {
-        let t = tile_ref.borrow();
-        if t.location().distance(loc) < dist {
-            closest = Some(Rc::clone(tile_ref));
-            dist = t.location().distance(loc);
+fn get_closest(g: &Grid, collection: &[Sighting], loc: Location) -> Option<Sighting> {
+    let mut closest: Option<Sighting> = None;
+    let mut dist = u32::MAX;
+    for (tile_ref, tile_loc) in collection {
+        if g.estimate(*tile_loc, loc) < dist {
+            closest = Some((Rc::clone(tile_ref), *tile_loc));
+            dist = g.estimate(*tile_loc, loc);
         }
     }
     closest
@@ -61,30 +121,43 @@ macro_rules! move_to {
             /// - No cached path exists
             /// - Target has moved (was collected by another agent)
             /// - Path is blocked
-            fn [<move_to_ $dest>](&mut self, g: &mut Grid, go: Object, tiles: &[Object], holes: &[Object]) {
+            /// Goes exploring when no destination can be perceived.
+            fn [<move_to_ $dest>](&mut self, g: &mut Grid, go: Object, tiles: &[Sighting], holes: &[Sighting]) {
                 let list = if stringify!($dest) == "hole" { holes } else { tiles };
                 let agent_location = self.location;
 
+                // Only keep the target while the agent still knows where it is
+                let known = self.$dest.as_ref().and_then(|cached| {
+                    list.iter().find(|(o, _)| Rc::ptr_eq(o, cached)).cloned()
+                });
+
                 // Get or update target
-                let target = if let Some(ref cached) = self.$dest {
-                    // Check if cached target is still the closest (it might have moved)
-                    let cached_loc = *cached.borrow().location();
-                    if Some(cached_loc) != self.cached_target_loc {
-                        // Target moved, need to find new closest and recalculate path
-                        self.cached_path.clear();
-                        self.cached_target_loc = None;
+                let (target, target_loc) = match known {
+                    Some((cached, cached_loc)) => {
+                        // Check if cached target is still the closest (it might have moved)
+                        if Some(cached_loc) != self.cached_target_loc {
+                            // Target moved, need to find new closest and recalculate path
+                            self.cached_path.clear();
+                            self.cached_target_loc = None;
+                        }
+                        (cached, cached_loc)
                     }
-                    Rc::clone(cached)
-                } else {
                     // No cached target, find closest
-                    let best = get_closest(list, agent_location).unwrap();
-                    self.$dest = Some(best.clone());
-                    self.cached_path.clear();
-                    best
+                    None => match get_closest(g, list, agent_location) {
+                        Some((best, best_loc)) => {
+                            self.$dest = Some(best.clone());
+                            self.cached_path.clear();
+                            (best, best_loc)
+                        }
+                        None => {
+                            debug!("no {} in sight", stringify!($dest));
+                            self.$dest = None;
+                            self.start_exploring();
+                            return;
+                        }
+                    },
                 };
 
-                let target_loc = *target.borrow().location();
-
                 // Check if we've arrived
                 if agent_location == target_loc {
                     self.cached_path.clear();
@@ -93,37 +166,7 @@ macro_rules! move_to {
                     return;
                 }
 
-                // Use cached path or calculate new one
-                if self.cached_path.is_empty() || self.cached_target_loc != Some(target_loc) {
-                    if let Some(path) = crate::astar::astar(g, agent_location, target_loc) {
-                        self.cached_path = path;
-                        self.cached_target_loc = Some(target_loc);
-                    } else {
-                        // No path found, clear cache and try again next frame
-                        self.cached_path.clear();
-                        self.cached_target_loc = None;
-                        return;
-                    }
-                }
-
-                // Follow the cached path
-                if !self.cached_path.is_empty() {
-                    let next_direction = self.cached_path[0];
-                    let next_location = agent_location.next_location(next_direction);
-                    debug!("next location: {next_location:?}");
-
-                    if g.is_free(next_location) || next_location == target_loc {
-                        debug!("allowed, moving");
-                        self.cached_path.remove(0);
-                        self.location = next_location;
-                        g.move_object(go, agent_location, next_location);
-                    } else {
-                        // Path is blocked, recalculate next frame
-                        debug!("blocked, will recalculate");
-                        self.cached_path.clear();
-                        self.cached_target_loc = None;
-                    }
-                }
+                self.follow_path(g, go, target_loc);
             }
         }
     }
@@ -134,6 +177,8 @@ impl AgentState {
         AgentState {
             location,
             id,
+            team: 1,
+            sightings_to: Recipient::Broadcast,
             score: 0,
             hole: None,
             tile: None,
@@ -141,34 +186,459 @@ impl AgentState {
             state: State::Idle,
             cached_path: Vec::new(),
             cached_target_loc: None,
+            wait: 0,
+            waypoint: None,
+            belief: BeliefMap::default(),
+            claims: Vec::new(),
+            reported: Vec::new(),
+            outbox: Vec::new(),
+            stats: AgentStats::default(),
+            expanded: Vec::new(),
         }
     }
 
+    /// Steps left on the path the agent is following
+    pub fn path_len(&self) -> usize {
+        self.cached_path.len()
+    }
+
+    /// Where the agent is heading while exploring
+    pub fn waypoint(&self) -> Option<Location> {
+        self.waypoint
+    }
+
+    /// The tile or hole the agent is heading for, or its waypoint while exploring
+    pub fn target(&self) -> Option<Location> {
+        let location = |o: &Option<Object>| o.as_ref().map(|o| *o.borrow().location());
+        match self.state {
+            State::MoveToTile => location(&self.tile),
+            State::MoveToHole => location(&self.hole),
+            State::Explore => self.waypoint,
+            State::Idle => None,
+        }
+    }
+
+    /// The cells along the path the agent is following, starting with the
+    /// next one
+    pub fn path(&self, g: &Grid) -> Vec<Location> {
+        self.cached_path
+            .iter()
+            .scan(self.location, |l, &d| {
+                *l = g.next_location(*l, d);
+                Some(*l)
+            })
+            .collect()
+    }
+
+    /// The cells expanded by the last path search
+    pub fn expanded(&self) -> &[Location] {
+        &self.expanded
+    }
+
     /// Clear cached path when changing targets
     fn clear_path_cache(&mut self) {
         self.cached_path.clear();
         self.cached_target_loc = None;
     }
 
+    /// Take one step along the cached path to `target_loc`, running A* first
+    /// when there is no path yet or it leads somewhere else. The target cell
+    /// may be entered even if it is occupied. Returns false when there is no
+    /// path to the target.
+    fn follow_path(&mut self, g: &mut Grid, go: Object, target_loc: Location) -> bool {
+        let agent_location = self.location;
+
+        // Use cached path or calculate new one
+        if self.cached_path.is_empty() || self.cached_target_loc != Some(target_loc) {
+            self.expanded.clear();
+            let path = if g.memory() {
+                // plan on what the agent believes, not on what is really there
+                let tick = g.tick();
+                crate::astar::astar_traced(
+                    g,
+                    agent_location,
+                    target_loc,
+                    |l| self.belief.is_blocked(l, tick),
+                    &mut self.expanded,
+                )
+            } else {
+                crate::astar::astar_traced(
+                    g,
+                    agent_location,
+                    target_loc,
+                    |l| !g.is_free(l),
+                    &mut self.expanded,
+                )
+            };
+            self.stats.plans += 1;
+            if let Some(path) = path {
+                self.cached_path = path;
+                self.cached_target_loc = Some(target_loc);
+            } else {
+                // No path found, clear cache and try again next frame
+                self.clear_path_cache();
+                return false;
+            }
+        }
+
+        // Follow the cached path
+        if !self.cached_path.is_empty() {
+            let next_direction = self.cached_path[0];
+            let next_location = g.next_location(agent_location, next_direction);
+            debug!("next location: {next_location:?}");
+
+            if g.can_move(agent_location, next_direction)
+                && (g.is_free(next_location) || next_location == target_loc)
+            {
+                debug!("allowed, moving");
+                self.cached_path.remove(0);
+                // one tick is spent moving, the rest of the cost is waited out
+                self.wait += g.step_cost(agent_location, next_direction) - STRAIGHT_COST;
+                self.location = next_location;
+                self.stats.steps += 1;
+                g.move_object(go, agent_location, next_location);
+            } else {
+                // Path is blocked, recalculate next frame
+                debug!("blocked, will recalculate");
+                self.stats.blocked += 1;
+                self.belief.bump(next_location, g.tick());
+                self.clear_path_cache();
+            }
+        }
+        true
+    }
+
+    /// The objects in `collection` this agent can currently perceive
+    fn perceive(&self, g: &Grid, collection: &[Object]) -> Vec<Sighting> {
+        collection
+            .iter()
+            .map(|o| (o.clone(), *o.borrow().location()))
+            .filter(|&(_, l)| g.sensor().can_see(g, self.location, l))
+            .collect()
+    }
+
+    /// The tiles and holes the agent knows about: the ones it perceives right
+    /// now, plus the ones it remembers (or heard about) when the grid has
+    /// memory enabled. Leaves out what other agents have claimed.
+    fn sense(
+        &mut self,
+        g: &mut Grid,
+        tiles: &[Object],
+        holes: &[Object],
+    ) -> (Vec<Sighting>, Vec<Sighting>) {
+        if !g.memory() {
+            return (self.perceive(g, tiles), self.perceive(g, holes));
+        }
+        self.belief.observe(g, self.location, tiles, holes);
+        if g.comms().is_some() {
+            self.listen(g);
+        }
+        let tiles = self.unclaimed(self.belief.tiles(), &self.tile);
+        let holes = self.unclaimed(self.belief.holes(), &self.hole);
+        (tiles, holes)
+    }
+
+    /// Handle the messages that arrived for this agent
+    fn listen(&mut self, g: &mut Grid) {
+        let tick = g.tick();
+        let topology = g.topology();
+        let Some(bus) = g.comms_mut() else {
+            return;
+        };
+        for message in bus.receive(self.id, self.team, self.location, tick, topology) {
+            debug!("agent {} got {message:?}", self.id);
+            match message.content {
+                Content::TileAt(ref t, l) => self.belief.hear_tile(t, l, message.sent_at),
+                Content::HoleAt(ref h, l) => self.belief.hear_hole(h, l, message.sent_at),
+                Content::Claim(ref o, l) => {
+                    let is_target =
+                        |target: &Option<Object>| target.as_ref().is_some_and(|t| Rc::ptr_eq(t, o));
+                    let contested = (self.state == State::MoveToTile && is_target(&self.tile))
+                        || (self.state == State::MoveToHole && is_target(&self.hole));
+                    if contested && message.from < self.id {
+                        // the lower id wins, find something else
+                        debug!("agent {} yields to agent {}", self.id, message.from);
+                        if self.state == State::MoveToTile {
+                            self.tile = None;
+                        } else {
+                            self.hole = None;
+                        }
+                        self.clear_path_cache();
+                    } else if contested {
+                        // tell the other agent to back off
+                        bus.send(
+                            Message {
+                                from: self.id,
+                                to: Recipient::Agent(message.from),
+                                content: Content::Claim(o.clone(), l),
+                                sent_at: tick,
+                            },
+                            self.location,
+                        );
+                    }
+                    self.claims.retain(|c| !Rc::ptr_eq(&c.object, o));
+                    self.claims.push(Claim {
+                        object: o.clone(),
+                        location: l,
+                        by: message.from,
+                        at: message.sent_at,
+                    });
+                }
+            }
+        }
+        self.claims.retain(|c| c.at + CLAIM_TIMEOUT > tick);
+    }
+
+    /// The sightings nobody else claimed. A claim by an agent with a higher
+    /// id doesn't count for `current`, the target this agent already has.
+    fn unclaimed(&self, sightings: &[Sighting], current: &Option<Object>) -> Vec<Sighting> {
+        sightings
+            .iter()
+            .filter(
+                |(o, l)| match claimed_by_other(&self.claims, o, *l, self.id) {
+                    None => true,
+                    Some(by) => by > self.id && current.as_ref().is_some_and(|c| Rc::ptr_eq(c, o)),
+                },
+            )
+            .cloned()
+            .collect()
+    }
+
+    /// Tell the other agents about tiles and holes seen this tick, and claim
+    /// the targets picked since `previous`
+    fn announce(&mut self, g: &mut Grid, previous: (Option<Object>, Option<Object>)) {
+        let tick = g.tick();
+        let mut outbox = std::mem::take(&mut self.outbox);
+        // a claim left over from last tick only counts while it is still the target
+        outbox.retain(|content| match content {
+            Content::Claim(o, _) => [&self.tile, &self.hole]
+                .into_iter()
+                .flatten()
+                .any(|t| Rc::ptr_eq(t, o)),
+            _ => true,
+        });
+        // what others told this tick is in the belief too, but isn't passed on
+        let seen_now = |l: Location| {
+            self.belief.cell(l).is_some_and(|(_, at)| at == tick)
+                && g.sensor().can_see(g, self.location, l)
+        };
+        for (o, l) in self.belief.tiles().iter().chain(self.belief.holes()) {
+            let already = self
+                .reported
+                .iter()
+                .any(|(r, rl)| rl == l && Rc::ptr_eq(r, o));
+            if seen_now(*l) && !already {
+                self.reported.retain(|(r, _)| !Rc::ptr_eq(r, o));
+                self.reported.push((o.clone(), *l));
+                outbox.push(match self.belief.cell(*l) {
+                    Some((Seen::Hole, _)) => Content::HoleAt(o.clone(), *l),
+                    _ => Content::TileAt(o.clone(), *l),
+                });
+            }
+        }
+        let changed = |now: &Option<Object>, before: &Option<Object>| match (now, before) {
+            (Some(now), Some(before)) => !Rc::ptr_eq(now, before),
+            (Some(_), None) => true,
+            _ => false,
+        };
+        let targets = [
+            (
+                State::MoveToTile,
+                &self.tile,
+                &previous.0,
+                self.belief.tiles(),
+            ),
+            (
+                State::MoveToHole,
+                &self.hole,
+                &previous.1,
+                self.belief.holes(),
+            ),
+        ];
+        for (state, target, before, known) in targets {
+            if self.state == state
+                && changed(target, before)
+                && let Some(target) = target
+                && let Some((_, l)) = known.iter().find(|(o, _)| Rc::ptr_eq(o, target))
+            {
+                outbox.push(Content::Claim(target.clone(), *l));
+            }
+        }
+        let Some(bus) = g.comms_mut() else {
+            return;
+        };
+        let mut sent = 0;
+        for content in &outbox {
+            let message = Message {
+                from: self.id,
+                to: match content {
+                    Content::Claim(..) => Recipient::Team(self.team),
+                    _ => self.sightings_to,
+                },
+                content: content.clone(),
+                sent_at: tick,
+            };
+            if !bus.send(message, self.location) {
+                break;
+            }
+            sent += 1;
+        }
+        // out of bandwidth, send the rest next tick
+        outbox.drain(..sent);
+        self.outbox = outbox;
+    }
+
+    fn start_exploring(&mut self) {
+        self.clear_path_cache();
+        self.waypoint = None;
+        self.state = State::Explore;
+    }
+
+    /// Walk between random waypoints until a tile comes into view, or a hole
+    /// when already carrying a tile
+    fn explore(&mut self, g: &mut Grid, go: Object, tiles: &[Sighting], holes: &[Sighting]) {
+        let agent_location = self.location;
+        if self.has_tile {
+            if let Some((best_hole, _)) = get_closest(g, holes, agent_location) {
+                debug!("spotted hole: {best_hole:?}");
+                self.hole = Some(best_hole);
+                self.clear_path_cache();
+                self.state = State::MoveToHole;
+                return;
+            }
+        } else if let Some((best_tile, _)) = get_closest(g, tiles, agent_location) {
+            debug!("spotted tile: {best_tile:?}");
+            self.tile = Some(best_tile);
+            self.clear_path_cache();
+            self.state = State::MoveToTile;
+            return;
+        }
+
+        // the waypoint must stay free, unlike tiles and holes it can't be entered when occupied
+        let waypoint = match self.waypoint {
+            Some(w) if w != agent_location && g.is_free(w) => w,
+            _ => {
+                let w = if g.memory() {
+                    // head for the parts of the grid seen longest ago
+                    self.belief.exploration_target(g)
+                } else {
+                    g.random_location()
+                };
+                match w {
+                    Some(w) => w,
+                    None => return,
+                }
+            }
+        };
+        self.waypoint = Some(waypoint);
+        if !self.follow_path(g, go, waypoint) {
+            // can't get there, pick another waypoint next tick
+            self.waypoint = None;
+        }
+    }
+
     pub fn update(&mut self, g: &mut Grid, go: Object, tiles: &[Object], holes: &[Object]) {
         debug!("agent {self:?}");
+        if self.wait >= STRAIGHT_COST {
+            self.wait -= STRAIGHT_COST;
+            self.stats.waited += 1;
+            return;
+        }
+        let (tiles, holes) = &self.sense(g, tiles, holes);
+        let previous = (self.tile.clone(), self.hole.clone());
         match self.state {
-            State::Idle => self.idle(tiles),
+            State::Idle => self.idle(g, tiles),
             State::MoveToTile => self.move_to_tile(g, go, tiles, holes),
             State::MoveToHole => self.move_to_hole(g, go, tiles, holes),
+            State::Explore => self.explore(g, go, tiles, holes),
+        }
+        if g.comms().is_some() {
+            self.announce(g, previous);
+        }
+    }
+
+    /// Carry out an action chosen from outside. Agents can step onto tiles
+    /// and holes, and pick up or drop a tile where they stand. Steps into
+    /// anything else or off the grid, and picks or drops where there is
+    /// nothing to pick up or drop into, are wasted.
+    pub fn act(
+        &mut self,
+        g: &mut Grid,
+        go: Object,
+        action: Action,
+        tiles: &[Object],
+        holes: &[Object],
+    ) {
+        debug!("agent {} does {action:?}", self.id);
+        if self.wait >= STRAIGHT_COST {
+            self.wait -= STRAIGHT_COST;
+            self.stats.waited += 1;
+            return;
+        }
+        if g.memory() {
+            // keeps the belief overlay meaningful, the agent doesn't plan on it
+            self.belief.observe(g, self.location, tiles, holes);
+        }
+        let here = self.location;
+        let at = |collection: &[Object], l: Location| {
+            collection
+                .iter()
+                .find(|o| *o.borrow().location() == l)
+                .cloned()
+        };
+        match action {
+            Action::Stay => {}
+            Action::Move(d) => {
+                if !g.can_move(here, d) {
+                    self.stats.blocked += 1;
+                    return;
+                }
+                let next = g.next_location(here, d);
+                if let Some(o) = g.object(next)
+                    && !matches!(*o.borrow(), GO::Tile(_) | GO::Hole(_))
+                {
+                    self.stats.blocked += 1;
+                    return;
+                }
+                self.wait += g.step_cost(here, d) - STRAIGHT_COST;
+                self.location = next;
+                self.stats.steps += 1;
+                g.move_object(go, here, next);
+                // the agent was hiding whatever it stood on
+                if let Some(under) = at(tiles, here).or_else(|| at(holes, here)) {
+                    g.insert_object(under, here);
+                }
+            }
+            Action::Pick => {
+                if !self.has_tile
+                    && let Some(tile) = at(tiles, here)
+                {
+                    self.tile = Some(tile.clone());
+                    self.pick_tile(g, go, &[], &[], here, tile);
+                }
+            }
+            Action::Drop => {
+                if self.has_tile
+                    && let Some(hole) = at(holes, here)
+                {
+                    self.hole = Some(hole.clone());
+                    self.dump_tile(g, go, &[], &[], here, hole);
+                }
+            }
         }
     }
 
-    fn idle(&mut self, tiles: &[Object]) {
+    fn idle(&mut self, g: &Grid, tiles: &[Sighting]) {
         let agent_location = self.location;
         debug!("current location: {agent_location:?}");
-        if let Some(best_tile) = get_closest(tiles, agent_location) {
+        if let Some((best_tile, _)) = get_closest(g, tiles, agent_location) {
             debug!("best tile: {best_tile:?}");
             self.tile = Some(Rc::clone(&best_tile));
             self.clear_path_cache(); // New target, clear cached path
             self.state = State::MoveToTile;
         } else {
             debug!("no best tile found");
+            self.start_exploring();
         }
     }
 
@@ -177,19 +647,24 @@ impl AgentState {
         &mut self,
         g: &mut Grid,
         go: Object,
-        _tiles: &[Object],
-        holes: &[Object],
+        _tiles: &[Sighting],
+        holes: &[Sighting],
         agent_location: Location,
         best_tile: Object,
     ) {
         self.has_tile = true;
+        self.stats.picked += 1;
         self.clear_path_cache(); // New target, clear cached path
-        if let Some(best_hole) = get_closest(holes, agent_location) {
+        if let Some((best_hole, _)) = get_closest(g, holes, agent_location) {
             self.hole = Some(Rc::clone(&best_hole));
             self.state = State::MoveToHole;
+        } else {
+            self.start_exploring();
         }
         // Teleport the tile to a new random location (respawn)
-        if let Some(new_location) = g.random_location() {
+        if !g.respawn() {
+            g.use_up(best_tile);
+        } else if let Some(new_location) = g.random_location() {
             best_tile.borrow_mut().set_location(new_location);
             g.move_object(best_tile, agent_location, new_location);
         }
@@ -204,28 +679,33 @@ impl AgentState {
         &mut self,
         g: &mut Grid,
         go: Object,
-        tiles: &[Object],
-        _holes: &[Object],
+        tiles: &[Sighting],
+        _holes: &[Sighting],
         agent_location: Location,
         best_hole: Object,
     ) {
         self.has_tile = false;
+        self.stats.dropped += 1;
         if let Some(t) = &self.tile.clone()
             && let GO::Tile(ref tstate) = *t.borrow()
         {
             self.score += tstate.score;
         }
         // Teleport the hole to a new random location (respawn)
-        if let Some(new_location) = g.random_location() {
+        if !g.respawn() {
+            g.use_up(best_hole);
+        } else if let Some(new_location) = g.random_location() {
             best_hole.borrow_mut().set_location(new_location);
             g.move_object(best_hole, agent_location, new_location);
         }
         self.location = agent_location;
         g.move_object(go, agent_location, agent_location);
         self.clear_path_cache(); // New target, clear cached path
-        if let Some(best_tile) = get_closest(tiles, agent_location) {
+        if let Some((best_tile, _)) = get_closest(g, tiles, agent_location) {
             self.tile = Some(Rc::clone(&best_tile));
             self.state = State::MoveToTile;
+        } else {
+            self.start_exploring();
         }
     }
 
@@ -243,12 +723,106 @@ pub struct HoleState {
     pub location: Location,
 }
 
+/// How an obstacle behaves over time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ObstacleKind {
+    /// Never moves
+    Static,
+    /// Takes a random step to a free neighbouring cell every tick
+    Wandering,
+    /// Stays in place but is open (passable) every other `period` ticks
+    Door { period: u64, phase: u64, open: bool },
+    /// Vanishes after `period` ticks and reappears at a random free location,
+    /// one where it doesn't cut off part of the grid
+    Blinking {
+        period: u64,
+        phase: u64,
+        visible: bool,
+    },
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct ObstacleState {
+    pub location: Location,
+    pub kind: ObstacleKind,
+}
+
+impl ObstacleState {
+    pub fn new(location: Location, kind: ObstacleKind) -> Self {
+        ObstacleState { location, kind }
+    }
+
+    /// Whether the obstacle currently occupies its cell on the grid
+    pub fn is_present(&self) -> bool {
+        match self.kind {
+            ObstacleKind::Door { open, .. } => !open,
+            ObstacleKind::Blinking { visible, .. } => visible,
+            _ => true,
+        }
+    }
+
+    /// Advance the obstacle by one tick. Only dynamic obstacles are updated.
+    /// Agents notice the change the next time they follow their cached path:
+    /// a blocked step invalidates the path and A* is run again.
+    pub fn update(&mut self, g: &mut Grid, go: Object) {
+        let tick = g.tick();
+        match self.kind {
+            ObstacleKind::Static => {}
+            ObstacleKind::Wandering => {
+                let d = DIRECTIONS[g.rng().random_range(0..DIRECTIONS.len())];
+                if g.can_move(self.location, d) {
+                    let next = g.next_location(self.location, d);
+                    if g.is_free(next) {
+                        g.move_object(go, self.location, next);
+                        self.location = next;
+                    }
+                }
+            }
+            ObstacleKind::Door {
+                period,
+                phase,
+                ref mut open,
+            } => {
+                let should_open = ((tick + phase) / period) % 2 == 1;
+                if should_open && !*open {
+                    debug!("door at {:?} opens", self.location);
+                    g.remove_object(self.location);
+                    *open = true;
+                } else if !should_open && *open && g.is_free(self.location) {
+                    // a door can only close once nothing is standing in it
+                    debug!("door at {:?} closes", self.location);
+                    g.insert_object(go, self.location);
+                    *open = false;
+                }
+            }
+            ObstacleKind::Blinking {
+                period,
+                phase,
+                ref mut visible,
+            } => {
+                let should_show = ((tick + phase) / period).is_multiple_of(2);
+                if !should_show && *visible {
+                    g.remove_object(self.location);
+                    *visible = false;
+                } else if should_show
+                    && !*visible
+                    && let Some(new_location) = g.random_connected_location()
+                {
+                    self.location = new_location;
+                    g.insert_object(go, new_location);
+                    *visible = true;
+                }
+            }
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Eq)]
 pub enum GO {
-    Agent(AgentState),
+    Agent(Box<AgentState>),
     Tile(TileState),
     Hole(HoleState),
-    Obstacle(Location),
+    Obstacle(ObstacleState),
 }
 
 impl GO {
@@ -257,16 +831,16 @@ impl GO {
             GO::Agent(a) => &a.location,
             GO::Tile(t) => &t.location,
             GO::Hole(h) => &h.location,
-            GO::Obstacle(o) => o,
+            GO::Obstacle(o) => &o.location,
         }
     }
 
-    fn set_location(&mut self, l: Location) {
+    pub fn set_location(&mut self, l: Location) {
         match self {
             GO::Agent(a) => a.location = l,
             GO::Tile(t) => t.location = l,
             GO::Hole(h) => h.location = l,
-            GO::Obstacle(_) => {}
+            GO::Obstacle(o) => o.location = l,
         }
     }
 
@@ -280,3 +854,6 @@ impl GO {
 }
 
 pub type Object = Rc<RefCell<GO>>;
+
+/// An object and where an agent believes it is
+pub type Sighting = (Object, Location);
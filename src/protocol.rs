@@ -0,0 +1,426 @@
+use crate::{
+    COLS, ROWS,
+    env::Env,
+    location::{Direction, Location},
+    objects::{Action, AgentInfo},
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashSet, hash::Hash};
+
+/// A location as [column, row]
+pub type Cell = [u16; 2];
+
+fn cell(l: Location) -> Cell {
+    [l.col, l.row]
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct TileView {
+    pub location: Cell,
+    pub score: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AgentView {
+    pub id: u8,
+    pub team: u8,
+    pub location: Cell,
+}
+
+/// What an agent perceives at the start of a tick, sent to it as one line of
+/// JSON. Only what its sensor can see is included.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Percept {
+    pub tick: u64,
+    pub id: u8,
+    pub team: u8,
+    pub location: Cell,
+    pub has_tile: bool,
+    pub score: u32,
+    pub tiles: Vec<TileView>,
+    pub holes: Vec<Cell>,
+    pub obstacles: Vec<Cell>,
+    /// The other agents
+    pub agents: Vec<AgentView>,
+}
+
+impl Percept {
+    pub fn new(env: &Env, agent: &AgentInfo) -> Self {
+        let g = env.grid();
+        let sees = |l: Location| g.sensor().can_see(g, agent.location, l);
+        let tiles = env
+            .tiles()
+            .iter()
+            .map(|t| (*t.borrow().location(), t.borrow().score()))
+            .filter(|&(l, _)| sees(l))
+            .map(|(l, score)| TileView {
+                location: cell(l),
+                score,
+            })
+            .collect();
+        let holes = env
+            .holes()
+            .iter()
+            .map(|h| *h.borrow().location())
+            .filter(|&l| sees(l))
+            .map(cell)
+            .collect();
+        let obstacles = (0..ROWS)
+            .flat_map(|r| (0..COLS).map(move |c| Location::new(c, r)))
+            .filter(|&l| g.is_obstacle(l) && sees(l))
+            .map(cell)
+            .collect();
+        let agents = env
+            .agent_info()
+            .iter()
+            .filter(|a| a.id != agent.id && sees(a.location))
+            .map(|a| AgentView {
+                id: a.id,
+                team: a.team,
+                location: cell(a.location),
+            })
+            .collect();
+        Percept {
+            tick: g.tick(),
+            id: agent.id,
+            team: agent.team,
+            location: cell(agent.location),
+            has_tile: agent.has_tile,
+            score: agent.score,
+            tiles,
+            holes,
+            obstacles,
+            agents,
+        }
+    }
+
+    pub fn to_line(&self) -> String {
+        // a percept is plain data, it always serializes
+        serde_json::to_string(self).expect("percept serializes") + "\n"
+    }
+}
+
+/// An action as sent by an agent: `{"action": "move", "direction": "up"}`,
+/// `{"action": "pick"}`, `{"action": "drop"}` or `{"action": "wait"}`
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "lowercase")]
+enum Command {
+    Move { direction: String },
+    Pick,
+    Drop,
+    Wait,
+}
+
+/// Decode one line of JSON sent by an agent
+pub fn parse_action(line: &str) -> Result<Action, String> {
+    let command: Command = serde_json::from_str(line.trim())
+        .map_err(|e| format!("invalid action '{}': {e}", line.trim()))?;
+    Ok(match command {
+        Command::Move { direction } => Action::Move(direction.parse::<Direction>()?),
+        Command::Pick => Action::Pick,
+        Command::Drop => Action::Drop,
+        Command::Wait => Action::Stay,
+    })
+}
+
+/// Encode an action as the line an agent sends
+pub fn action_line(action: Action) -> String {
+    let command = match action {
+        Action::Move(d) => Command::Move {
+            direction: d.to_string(),
+        },
+        Action::Pick => Command::Pick,
+        Action::Drop => Command::Drop,
+        Action::Stay => Command::Wait,
+    };
+    serde_json::to_string(&command).expect("command serializes") + "\n"
+}
+
+/// The first line a client sends to the server, to play an agent or to watch:
+/// `{"role": "agent", "id": 2}` or `{"role": "spectator"}`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "role", rename_all = "lowercase")]
+pub enum Hello {
+    Agent { id: u8 },
+    Spectator,
+}
+
+/// The server's answer to a hello: `{"type": "welcome"}`, or an error after
+/// which the connection is closed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Reply {
+    Welcome,
+    Error { message: String },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AgentStatus {
+    pub id: u8,
+    pub team: u8,
+    pub location: Cell,
+    pub has_tile: bool,
+    pub score: u32,
+}
+
+/// Everything on the grid, as shown to spectators
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct World {
+    pub episode: u32,
+    pub tick: u64,
+    pub agents: Vec<AgentStatus>,
+    pub tiles: Vec<TileView>,
+    pub holes: Vec<Cell>,
+    pub obstacles: Vec<Cell>,
+}
+
+/// What changed on the grid since the previous tick. Empty lists are left out.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WorldDiff {
+    pub tick: u64,
+    /// Agents that moved, scored, or picked up or dropped a tile, and new ones
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub agents: Vec<AgentStatus>,
+    /// Ids of the agents taken off the grid
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub agents_removed: Vec<u8>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tiles_added: Vec<TileView>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tiles_removed: Vec<Cell>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub holes_added: Vec<Cell>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub holes_removed: Vec<Cell>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub obstacles_added: Vec<Cell>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub obstacles_removed: Vec<Cell>,
+}
+
+/// A message to a spectator: the whole world when it joins and when a new
+/// episode starts, the changes after every other tick
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Update {
+    Snapshot(World),
+    Diff(WorldDiff),
+}
+
+impl Update {
+    pub fn to_line(&self) -> String {
+        serde_json::to_string(self).expect("update serializes") + "\n"
+    }
+}
+
+/// The items of `new` that aren't in `old`
+fn added<T: Clone + Eq + Hash>(old: &[T], new: &[T]) -> Vec<T> {
+    let old: HashSet<&T> = old.iter().collect();
+    new.iter().filter(|x| !old.contains(x)).cloned().collect()
+}
+
+impl World {
+    pub fn new(env: &Env) -> Self {
+        let g = env.grid();
+        let agents = env
+            .agent_info()
+            .iter()
+            .map(|a| AgentStatus {
+                id: a.id,
+                team: a.team,
+                location: cell(a.location),
+                has_tile: a.has_tile,
+                score: a.score,
+            })
+            .collect();
+        let tiles = env
+            .tiles()
+            .iter()
+            .map(|t| TileView {
+                location: cell(*t.borrow().location()),
+                score: t.borrow().score(),
+            })
+            .collect();
+        let holes = env
+            .holes()
+            .iter()
+            .map(|h| cell(*h.borrow().location()))
+            .collect();
+        let obstacles = (0..ROWS)
+            .flat_map(|r| (0..COLS).map(move |c| Location::new(c, r)))
+            .filter(|&l| g.is_obstacle(l))
+            .map(cell)
+            .collect();
+        let mut world = World {
+            episode: env.episode().number,
+            tick: g.tick(),
+            agents,
+            tiles,
+            holes,
+            obstacles,
+        };
+        world.sort();
+        world
+    }
+
+    // a fixed order, so worlds can be compared
+    fn sort(&mut self) {
+        self.agents.sort_by_key(|a| a.id);
+        self.tiles.sort();
+        self.holes.sort();
+        self.obstacles.sort();
+    }
+
+    /// The changes that turn this world into `next`
+    pub fn diff(&self, next: &World) -> WorldDiff {
+        let removed_tiles = added(&next.tiles, &self.tiles);
+        let ids = |world: &World| world.agents.iter().map(|a| a.id).collect::<Vec<_>>();
+        WorldDiff {
+            tick: next.tick,
+            agents: next
+                .agents
+                .iter()
+                .filter(|a| !self.agents.contains(a))
+                .cloned()
+                .collect(),
+            agents_removed: added(&ids(next), &ids(self)),
+            tiles_added: added(&self.tiles, &next.tiles),
+            tiles_removed: removed_tiles.iter().map(|t| t.location).collect(),
+            holes_added: added(&self.holes, &next.holes),
+            holes_removed: added(&next.holes, &self.holes),
+            obstacles_added: added(&self.obstacles, &next.obstacles),
+            obstacles_removed: added(&next.obstacles, &self.obstacles),
+        }
+    }
+
+    /// Bring the world up to date with `diff`
+    pub fn apply(&mut self, diff: &WorldDiff) {
+        self.tick = diff.tick;
+        self.agents.retain(|a| !diff.agents_removed.contains(&a.id));
+        for agent in &diff.agents {
+            self.agents.retain(|a| a.id != agent.id);
+            self.agents.push(agent.clone());
+        }
+        self.tiles
+            .retain(|t| !diff.tiles_removed.contains(&t.location));
+        self.tiles.extend(diff.tiles_added.iter().cloned());
+        self.holes.retain(|h| !diff.holes_removed.contains(h));
+        self.holes.extend(&diff.holes_added);
+        self.obstacles
+            .retain(|o| !diff.obstacles_removed.contains(o));
+        self.obstacles.extend(&diff.obstacles_added);
+        self.sort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::Config, map::Item, sensor::Sensor};
+
+    #[test]
+    fn test_parse_action() {
+        assert_eq!(
+            parse_action(r#"{"action": "move", "direction": "left"}"#),
+            Ok(Action::Move(Direction::Left))
+        );
+        assert_eq!(parse_action("{\"action\":\"pick\"}\n"), Ok(Action::Pick));
+        assert_eq!(parse_action(r#"{"action": "drop"}"#), Ok(Action::Drop));
+        assert_eq!(parse_action(r#"{"action": "wait"}"#), Ok(Action::Stay));
+        assert!(parse_action(r#"{"action": "move", "direction": "north"}"#).is_err());
+        assert!(parse_action(r#"{"action": "fly"}"#).is_err());
+        assert!(parse_action("up").is_err());
+        for action in [
+            Action::Move(Direction::DownRight),
+            Action::Pick,
+            Action::Drop,
+            Action::Stay,
+        ] {
+            assert_eq!(parse_action(&action_line(action)), Ok(action));
+        }
+    }
+
+    #[test]
+    fn test_hello_and_reply() {
+        assert_eq!(
+            serde_json::from_str::<Hello>(r#"{"role": "agent", "id": 2}"#).unwrap(),
+            Hello::Agent { id: 2 }
+        );
+        assert_eq!(
+            serde_json::from_str::<Hello>(r#"{"role": "spectator"}"#).unwrap(),
+            Hello::Spectator
+        );
+        assert_eq!(
+            serde_json::to_string(&Reply::Welcome).unwrap(),
+            r#"{"type":"welcome"}"#
+        );
+    }
+
+    #[test]
+    fn test_diffs_replay_the_world() {
+        let config = Config {
+            agents: 3,
+            wandering_obstacles: 3,
+            ..Config::default()
+        };
+        let mut env = Env::new(config);
+        let mut world = World::new(&env);
+        for _ in 0..30 {
+            env.step(&[]);
+            let next = World::new(&env);
+            let update = Update::Diff(world.diff(&next));
+            let Ok(Update::Diff(diff)) = serde_json::from_str(&update.to_line()) else {
+                panic!("diff doesn't roundtrip");
+            };
+            world.apply(&diff);
+            assert_eq!(world, next);
+        }
+        assert!(world.diff(&world.clone()).agents.is_empty());
+
+        // agents taken off the grid and put back in the editor
+        let l = env.agent_info()[1].location;
+        env.clear(l);
+        let next = World::new(&env);
+        let diff = world.diff(&next);
+        assert_eq!(diff.agents_removed, vec![2]);
+        world.apply(&diff);
+        assert_eq!(world, next);
+        let free = env.grid().random_location().unwrap();
+        assert!(env.place(free, Item::Agent));
+        let next = World::new(&env);
+        let diff = world.diff(&next);
+        assert_eq!(
+            diff.agents.iter().map(|a| a.id).collect::<Vec<_>>(),
+            vec![2]
+        );
+        world.apply(&diff);
+        assert_eq!(world, next);
+    }
+
+    #[test]
+    fn test_percept_only_holds_what_the_sensor_sees() {
+        let config = Config {
+            agents: 2,
+            sensor: Sensor::Radius {
+                radius: 3,
+                line_of_sight: false,
+            },
+            ..Config::default()
+        };
+        let env = Env::new(config);
+        let agent = &env.agent_info()[0];
+        let percept = Percept::new(&env, agent);
+        assert_eq!(percept.id, 1);
+        assert!(percept.agents.iter().all(|a| a.id != 1));
+        for t in &percept.tiles {
+            let (dc, dr) = (
+                t.location[0].abs_diff(agent.location.col),
+                t.location[1].abs_diff(agent.location.row),
+            );
+            assert!(dc * dc + dr * dr <= 9);
+        }
+        let line = percept.to_line();
+        assert!(line.ends_with('\n') && !line.trim_end().contains('\n'));
+        assert_eq!(serde_json::from_str::<Percept>(&line).unwrap(), percept);
+    }
+}
@@ -0,0 +1,350 @@
+use crate::{
+    config::Config,
+    env::Env,
+    grid::Grid,
+    location::{DIRECTIONS, Location},
+    objects::{Action, AgentInfo, GO, Object},
+};
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use std::{fs, io, path::Path};
+
+/// Where the nearest target is (9 headings including "right here", or not in
+/// sight), whether the agent carries a tile, and which of the four
+/// neighbouring cells are blocked
+pub const STATES: usize = 10 * 2 * 16;
+/// Straight steps (whatever the movement model), picking up and dropping
+pub const ACTIONS: usize = DIRECTIONS.len() + 2;
+
+/// The action with index `i` in the table
+fn action(i: usize) -> Action {
+    match i {
+        i if i < DIRECTIONS.len() => Action::Move(DIRECTIONS[i]),
+        4 => Action::Pick,
+        _ => Action::Drop,
+    }
+}
+
+/// How long a training or evaluation episode lasts when the game has no time limit
+pub const EPISODE_TICKS: u64 = 500;
+
+/// Learning rate, discount and exploration of the Q-learning agent
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QParams {
+    pub alpha: f32,
+    pub gamma: f32,
+    pub epsilon: f32,
+}
+
+impl Default for QParams {
+    fn default() -> Self {
+        QParams {
+            alpha: 0.1,
+            gamma: 0.9,
+            epsilon: 0.1,
+        }
+    }
+}
+
+/// A tabular Q-learning agent, a baseline learner for the environment
+#[derive(Debug, Clone)]
+pub struct QAgent {
+    table: Vec<[f32; ACTIONS]>,
+    params: QParams,
+    rng: StdRng,
+}
+
+impl QAgent {
+    pub fn new(params: QParams, seed: u64) -> Self {
+        QAgent {
+            table: vec![[0.0; ACTIONS]; STATES],
+            params,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    /// The state of agent `agent` as the table index: the heading to the
+    /// nearest tile it can see, or the nearest hole while carrying one
+    pub fn state(g: &Grid, agent: &AgentInfo, tiles: &[Object], holes: &[Object]) -> usize {
+        let targets = if agent.has_tile { holes } else { tiles };
+        let nearest = targets
+            .iter()
+            .map(|o| *o.borrow().location())
+            .filter(|&l| g.sensor().can_see(g, agent.location, l))
+            .min_by_key(|&l| g.estimate(agent.location, l));
+        let heading = match nearest {
+            Some(l) => {
+                let (dc, dr) = g.topology().heading(agent.location, l);
+                ((dr + 1) * 3 + dc + 1) as usize
+            }
+            None => 9,
+        };
+        let mut blocked = 0;
+        for (i, &d) in DIRECTIONS.iter().enumerate() {
+            let next = g.next_location(agent.location, d);
+            if !g.can_move(agent.location, d) || is_in_the_way(g, next) {
+                blocked |= 1 << i;
+            }
+        }
+        (heading * 2 + usize::from(agent.has_tile)) * 16 + blocked
+    }
+
+    /// Pick an action for `state`, a random one now and then when exploring
+    pub fn choose(&mut self, state: usize, explore: bool) -> usize {
+        if explore && self.rng.random::<f32>() < self.params.epsilon {
+            return self.rng.random_range(0..ACTIONS);
+        }
+        let values = &self.table[state];
+        // ties go to a random action, or an untrained agent always walks up
+        let best = values.iter().copied().fold(f32::MIN, f32::max);
+        let candidates: Vec<usize> = (0..ACTIONS).filter(|&a| values[a] == best).collect();
+        candidates[self.rng.random_range(0..candidates.len())]
+    }
+
+    /// Move the value of taking `action` in `state` towards the reward plus
+    /// the discounted value of the best action in `next`
+    pub fn learn(&mut self, state: usize, action: usize, reward: f32, next: usize) {
+        let QParams { alpha, gamma, .. } = self.params;
+        let best_next = self.table[next].iter().copied().fold(f32::MIN, f32::max);
+        let value = &mut self.table[state][action];
+        *value += alpha * (reward + gamma * best_next - *value);
+    }
+
+    /// Write the table as text, one state per line
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let lines: Vec<String> = self
+            .table
+            .iter()
+            .map(|values| {
+                let values: Vec<String> = values.iter().map(f32::to_string).collect();
+                values.join(" ")
+            })
+            .collect();
+        fs::write(path, lines.join("\n") + "\n")
+    }
+
+    /// Read a table written by `save`
+    pub fn load(path: &Path, params: QParams, seed: u64) -> io::Result<Self> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+        let text = fs::read_to_string(path)?;
+        let mut agent = QAgent::new(params, seed);
+        let lines: Vec<&str> = text.lines().collect();
+        if lines.len() != STATES {
+            return Err(invalid(format!(
+                "expected {STATES} states, found {}",
+                lines.len()
+            )));
+        }
+        for (state, line) in lines.iter().enumerate() {
+            let values: Vec<f32> = line
+                .split_whitespace()
+                .map(str::parse)
+                .collect::<Result<_, _>>()
+                .map_err(|e| invalid(format!("line {}: {e}", state + 1)))?;
+            agent.table[state] = values
+                .try_into()
+                .map_err(|_| invalid(format!("line {}: expected {ACTIONS} values", state + 1)))?;
+        }
+        Ok(agent)
+    }
+
+    /// The state of each of the first `controlled` agents and the action chosen in it
+    fn choose_all(&mut self, env: &Env, controlled: usize, explore: bool) -> Vec<(usize, usize)> {
+        let (tiles, holes) = (env.tiles(), env.holes());
+        env.agent_info()
+            .iter()
+            .take(controlled)
+            .map(|a| {
+                let state = QAgent::state(env.grid(), a, &tiles, &holes);
+                (state, self.choose(state, explore))
+            })
+            .collect()
+    }
+
+    /// Pick the next move for the first `controlled` agents of `env`, without exploring
+    pub fn actions(&mut self, env: &Env, controlled: usize) -> Vec<Action> {
+        self.choose_all(env, controlled, false)
+            .into_iter()
+            .map(|(_, i)| action(i))
+            .collect()
+    }
+}
+
+/// Whether the cell at `l` can't be stepped onto
+fn is_in_the_way(g: &Grid, l: Location) -> bool {
+    g.object(l)
+        .is_some_and(|o| matches!(*o.borrow(), GO::Obstacle(_) | GO::Agent(_)))
+}
+
+/// `config` with a time limit for episodes that would otherwise run forever
+fn episode_config(config: &Config) -> Config {
+    let mut config = config.clone();
+    config.game.time_limit.get_or_insert(EPISODE_TICKS);
+    config
+}
+
+/// Train `agent` for a number of episodes in which it controls every agent,
+/// all sharing one table. Returns the total score of each episode.
+pub fn train(config: &Config, agent: &mut QAgent, episodes: u32) -> Vec<u32> {
+    let mut env = Env::new(episode_config(config));
+    let controlled = env.agents().len();
+    let mut scores = vec![];
+    for _ in 0..episodes {
+        env.reset(None);
+        loop {
+            let was_carrying: Vec<bool> = env.agent_info().iter().map(|a| a.has_tile).collect();
+            let choices = agent.choose_all(&env, controlled, true);
+            let actions: Vec<Action> = choices.iter().map(|&(_, i)| action(i)).collect();
+            let (_, rewards, done, _) = env.step(&actions);
+            let (tiles, holes) = (env.tiles(), env.holes());
+            for (i, info) in env.agent_info().iter().enumerate() {
+                // picking up a tile is worth something too, or the agent
+                // hardly ever finds out what a hole is for
+                let picked_up = info.has_tile && !was_carrying[i];
+                let reward = rewards[i] + if picked_up { 1.0 } else { 0.0 } - 0.01;
+                let next = QAgent::state(env.grid(), info, &tiles, &holes);
+                let (state, action) = choices[i];
+                agent.learn(state, action, reward, next);
+            }
+            if done {
+                break;
+            }
+        }
+        scores.push(env.agent_info().iter().map(|a| a.score).sum());
+    }
+    scores
+}
+
+/// Play episodes with agent 1 controlled by `agent`, and the same episodes
+/// again (same seeds) with agent 1 following the built-in planner. Returns
+/// the average score of agent 1 in both cases.
+pub fn evaluate(config: &Config, agent: &mut QAgent, episodes: u32, seed: u64) -> (f32, f32) {
+    let config = episode_config(config);
+    let mut totals = [0u32; 2];
+    for episode in 0..episodes {
+        for (learned, total) in [true, false].into_iter().zip(&mut totals) {
+            let mut env = Env::new(config.clone());
+            env.reset(Some(seed.wrapping_add(u64::from(episode))));
+            loop {
+                let actions = if learned {
+                    agent.actions(&env, 1)
+                } else {
+                    vec![]
+                };
+                let (_, _, done, _) = env.step(&actions);
+                if done {
+                    break;
+                }
+            }
+            *total += env.agent_info().first().map_or(0, |a| a.score);
+        }
+    }
+    let average = |total: u32| total as f32 / episodes.max(1) as f32;
+    (average(totals[0]), average(totals[1]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        location::Topology,
+        objects::{GO, HoleState, TileState},
+    };
+    use std::{cell::RefCell, rc::Rc};
+
+    fn agent_at(location: Location, has_tile: bool) -> AgentInfo {
+        AgentInfo {
+            id: 1,
+            team: 1,
+            score: 0,
+            location,
+            has_tile,
+        }
+    }
+
+    #[test]
+    fn test_state_encoding() {
+        let mut grid = Grid::new();
+        let (tile, hole) = (Location::new(10, 10), Location::new(20, 20));
+        let tiles = [Rc::new(RefCell::new(GO::Tile(TileState {
+            location: tile,
+            score: 1,
+        })))];
+        let holes = [Rc::new(RefCell::new(GO::Hole(HoleState {
+            location: hole,
+        })))];
+        grid.insert_object(tiles[0].clone(), tile);
+        grid.insert_object(holes[0].clone(), hole);
+        let state = |l: Location, has_tile: bool| {
+            QAgent::state(&grid, &agent_at(l, has_tile), &tiles, &holes)
+        };
+        assert!(state(tile, false) < STATES);
+        // standing on the tile: heading 4 is "right here"
+        assert_eq!(state(tile, false) / 32, 4);
+        assert_eq!(state(hole, true) / 32, 4);
+        assert_eq!(state(hole, true) / 16 % 2, 1);
+        // the corner can't go up or left
+        let corner = Location::new(0, 0);
+        assert_eq!(state(corner, false) & 0b0101, 0b0101);
+        let mut empty = Grid::new();
+        empty.set_topology(Topology::Torus);
+        assert_eq!(
+            QAgent::state(&empty, &agent_at(corner, false), &[], &[]),
+            9 * 32
+        );
+    }
+
+    #[test]
+    fn test_learn_moves_towards_reward() {
+        let mut agent = QAgent::new(QParams::default(), 0);
+        agent.learn(5, 2, 1.0, 6);
+        assert!((agent.table[5][2] - 0.1).abs() < 1e-6);
+        assert_eq!(agent.choose(5, false), 2);
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let mut agent = QAgent::new(QParams::default(), 0);
+        agent.learn(100, 3, 2.5, 7);
+        let path = std::env::temp_dir().join(format!("tileworld-q-{}.txt", std::process::id()));
+        agent.save(&path).unwrap();
+        let loaded = QAgent::load(&path, QParams::default(), 0).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.table, agent.table);
+    }
+
+    #[test]
+    fn test_evaluate_without_agents() {
+        let config = Config {
+            agents: 0,
+            ..Config::default()
+        };
+        let mut agent = QAgent::new(QParams::default(), 0);
+        assert_eq!(evaluate(&config, &mut agent, 1, 0), (0.0, 0.0));
+        // the seeds of later episodes wrap around
+        assert_eq!(evaluate(&config, &mut agent, 2, u64::MAX), (0.0, 0.0));
+    }
+
+    #[test]
+    fn test_training_learns_to_score() {
+        let mut config = Config {
+            agents: 2,
+            obstacles: 5,
+            seed: Some(1),
+            ..Config::default()
+        };
+        config.game.time_limit = Some(200);
+        let mut untrained = QAgent::new(QParams::default(), 1);
+        let (before, _) = evaluate(&config, &mut untrained, 2, 100);
+        let mut agent = QAgent::new(QParams::default(), 1);
+        train(&config, &mut agent, 10);
+        let (learned, _) = evaluate(&config, &mut agent, 2, 100);
+        assert!(
+            learned > before,
+            "{learned} after training, {before} before"
+        );
+        // standing on a tile it picks it up, and on a hole while carrying one it drops it
+        let here = 4 * 32;
+        assert_eq!(agent.choose(here, false), 4);
+        assert_eq!(agent.choose(here + 16, false), 5);
+    }
+}
@@ -0,0 +1,473 @@
+use crate::{
+    FONT,
+    clock::Clock,
+    draw,
+    env::Env,
+    view::{View, WINDOW_SIZE},
+};
+use graphics::{
+    Context, DrawState, Graphics, ImageSize, glyph_cache::rusttype::GlyphCache, types::Color,
+};
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter},
+    path::{Path, PathBuf},
+};
+use texture::{CreateTexture, Format, TextureOp, TextureSettings, UpdateTexture};
+
+/// Hundredths of a second each frame of a GIF is shown
+const GIF_DELAY: u16 = 10;
+
+/// An RGBA image in memory, what glyphs are drawn from
+#[derive(Debug, Clone)]
+pub struct Texture {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+impl Texture {
+    /// Color of the pixel at `[u, v]`, scaled to 0..1 across the texture
+    fn sample(&self, [u, v]: [f32; 2]) -> Color {
+        let x = ((u * self.width as f32) as u32).min(self.width.saturating_sub(1));
+        let y = ((v * self.height as f32) as u32).min(self.height.saturating_sub(1));
+        let i = ((y * self.width + x) * 4) as usize;
+        match self.pixels.get(i..i + 4) {
+            Some(&[r, g, b, a]) => [r, g, b, a].map(|c| f32::from(c) / 255.0),
+            _ => [0.0; 4],
+        }
+    }
+}
+
+impl ImageSize for Texture {
+    fn get_size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+}
+
+impl TextureOp<()> for Texture {
+    type Error = String;
+}
+
+impl CreateTexture<()> for Texture {
+    fn create<S: Into<[u32; 2]>>(
+        _: &mut (),
+        _: Format,
+        memory: &[u8],
+        size: S,
+        _: &TextureSettings,
+    ) -> Result<Self, Self::Error> {
+        let [width, height] = size.into();
+        let len = (width * height * 4) as usize;
+        if memory.len() < len {
+            return Err(format!(
+                "{} bytes for a {width}x{height} texture",
+                memory.len()
+            ));
+        }
+        Ok(Texture {
+            width,
+            height,
+            pixels: memory[..len].to_vec(),
+        })
+    }
+}
+
+impl UpdateTexture<()> for Texture {
+    fn update<O, S>(
+        &mut self,
+        _: &mut (),
+        _: Format,
+        memory: &[u8],
+        offset: O,
+        size: S,
+    ) -> Result<(), Self::Error>
+    where
+        O: Into<[u32; 2]>,
+        S: Into<[u32; 2]>,
+    {
+        let ([left, top], [width, height]) = (offset.into(), size.into());
+        if left + width > self.width
+            || top + height > self.height
+            || memory.len() < (width * height * 4) as usize
+        {
+            return Err(format!(
+                "a {width}x{height} update doesn't fit at ({left}, {top})"
+            ));
+        }
+        for row in 0..height {
+            let from = (row * width * 4) as usize;
+            let to = (((top + row) * self.width + left) * 4) as usize;
+            let len = (width * 4) as usize;
+            self.pixels[to..to + len].copy_from_slice(&memory[from..from + len]);
+        }
+        Ok(())
+    }
+}
+
+/// Text in images, in the font the window uses
+pub type Glyphs = GlyphCache<'static, (), Texture>;
+
+pub fn glyphs() -> Glyphs {
+    GlyphCache::from_bytes(FONT, (), TextureSettings::new()).expect("failed to load font")
+}
+
+/// An RGB image that the drawing functions can draw on like on the window,
+/// without a GPU or a display
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Canvas {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+impl Canvas {
+    /// A white canvas
+    pub fn new(width: u32, height: u32) -> Self {
+        Canvas {
+            width,
+            height,
+            pixels: vec![255; (width * height * 3) as usize],
+        }
+    }
+
+    /// For drawing in pixels from the top left corner
+    pub fn context(&self) -> Context {
+        Context::new_abs(f64::from(self.width), f64::from(self.height))
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 3] {
+        let i = ((y * self.width + x) * 3) as usize;
+        [self.pixels[i], self.pixels[i + 1], self.pixels[i + 2]]
+    }
+
+    /// Red, green and blue of every pixel, row by row
+    pub fn as_rgb(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    pub fn save_png(&self, path: &Path) -> io::Result<()> {
+        let mut encoder =
+            png::Encoder::new(BufWriter::new(File::create(path)?), self.width, self.height);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().map_err(io::Error::other)?;
+        writer
+            .write_image_data(&self.pixels)
+            .map_err(io::Error::other)?;
+        writer.finish().map_err(io::Error::other)
+    }
+
+    fn blend(&mut self, x: u32, y: u32, [r, g, b, a]: Color) {
+        let i = ((y * self.width + x) * 3) as usize;
+        for (dst, src) in self.pixels[i..i + 3].iter_mut().zip([r, g, b]) {
+            let mixed = src.clamp(0.0, 1.0) * a + f32::from(*dst) / 255.0 * (1.0 - a);
+            *dst = (mixed * 255.0).round() as u8;
+        }
+    }
+
+    /// Fill the triangles in `vertices`, given in the -1..1 coordinates the
+    /// drawing functions hand to the back end, with the color `shade` gives
+    /// for each pixel's barycentric weights, inside the scissor rectangle
+    fn fill(
+        &mut self,
+        draw_state: &DrawState,
+        vertices: &[[f32; 2]],
+        shade: impl Fn(usize, [f32; 3]) -> Color,
+    ) {
+        let (width, height) = (self.width as f32, self.height as f32);
+        let [left, top, right, bottom] = match draw_state.scissor {
+            Some([x, y, w, h]) => [x, y, x.saturating_add(w), y.saturating_add(h)],
+            None => [0, 0, self.width, self.height],
+        };
+        for (t, triangle) in vertices.chunks_exact(3).enumerate() {
+            let [a, b, c] = [0, 1, 2].map(|i| {
+                let [x, y] = triangle[i];
+                [(x + 1.0) / 2.0 * width, (1.0 - y) / 2.0 * height]
+            });
+            let edge = |p: [f32; 2], q: [f32; 2], s: [f32; 2]| {
+                (q[0] - p[0]) * (s[1] - p[1]) - (q[1] - p[1]) * (s[0] - p[0])
+            };
+            let area = edge(a, b, c);
+            if area == 0.0 {
+                continue;
+            }
+            let min = |i: usize| a[i].min(b[i]).min(c[i]).floor().max(0.0) as u32;
+            let max = |i: usize, limit: f32| a[i].max(b[i]).max(c[i]).ceil().min(limit) as u32;
+            for y in min(1).max(top)..max(1, height).min(bottom) {
+                for x in min(0).max(left)..max(0, width).min(right) {
+                    // sampling slightly off the pixel center keeps pixels on
+                    // an edge shared by two triangles from being drawn twice
+                    let s = [x as f32 + 0.5001, y as f32 + 0.5003];
+                    let weights = [
+                        edge(b, c, s) / area,
+                        edge(c, a, s) / area,
+                        edge(a, b, s) / area,
+                    ];
+                    if weights.iter().all(|&w| w >= 0.0) {
+                        let color = shade(3 * t, weights);
+                        if color[3] > 0.0 {
+                            self.blend(x, y, color);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Mix the three values of a triangle by barycentric weights
+fn mix<const N: usize>(values: &[[f32; N]], weights: [f32; 3]) -> [f32; N] {
+    std::array::from_fn(|i| (0..3).map(|v| values[v][i] * weights[v]).sum())
+}
+
+fn tint(a: Color, b: Color) -> Color {
+    [a[0] * b[0], a[1] * b[1], a[2] * b[2], a[3] * b[3]]
+}
+
+impl Graphics for Canvas {
+    type Texture = Texture;
+
+    fn clear_color(&mut self, [r, g, b, _]: Color) {
+        let rgb = [r, g, b].map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8);
+        for pixel in self.pixels.chunks_exact_mut(3) {
+            pixel.copy_from_slice(&rgb);
+        }
+    }
+
+    fn clear_stencil(&mut self, _: u8) {}
+
+    fn tri_list<F>(&mut self, draw_state: &DrawState, color: &[f32; 4], mut f: F)
+    where
+        F: FnMut(&mut dyn FnMut(&[[f32; 2]])),
+    {
+        f(&mut |vertices| self.fill(draw_state, vertices, |_, _| *color));
+    }
+
+    fn tri_list_c<F>(&mut self, draw_state: &DrawState, mut f: F)
+    where
+        F: FnMut(&mut dyn FnMut(&[[f32; 2]], &[[f32; 4]])),
+    {
+        f(&mut |vertices, colors| {
+            self.fill(draw_state, vertices, |i, w| mix(&colors[i..i + 3], w))
+        });
+    }
+
+    fn tri_list_uv<F>(
+        &mut self,
+        draw_state: &DrawState,
+        color: &[f32; 4],
+        texture: &Texture,
+        mut f: F,
+    ) where
+        F: FnMut(&mut dyn FnMut(&[[f32; 2]], &[[f32; 2]])),
+    {
+        f(&mut |vertices, uvs| {
+            self.fill(draw_state, vertices, |i, w| {
+                tint(*color, texture.sample(mix(&uvs[i..i + 3], w)))
+            })
+        });
+    }
+
+    fn tri_list_uv_c<F>(&mut self, draw_state: &DrawState, texture: &Texture, mut f: F)
+    where
+        F: FnMut(&mut dyn FnMut(&[[f32; 2]], &[[f32; 2]], &[[f32; 4]])),
+    {
+        f(&mut |vertices, uvs, colors| {
+            self.fill(draw_state, vertices, |i, w| {
+                tint(
+                    mix(&colors[i..i + 3], w),
+                    texture.sample(mix(&uvs[i..i + 3], w)),
+                )
+            })
+        });
+    }
+}
+
+/// The scene the window shows, drawn on a canvas of the same size
+pub fn render(env: &Env, clock: &Clock, glyphs: &mut Glyphs) -> Canvas {
+    let [width, height] = WINDOW_SIZE;
+    let mut canvas = Canvas::new(width as u32, height as u32);
+    let ctx = canvas.context();
+    draw::draw(
+        env,
+        clock,
+        &View::new(WINDOW_SIZE),
+        glyphs,
+        &ctx,
+        &mut canvas,
+    );
+    canvas
+}
+
+/// Saves frames of a run as numbered PNG files in a directory, as an animated
+/// GIF, or both
+pub struct Recorder {
+    glyphs: Glyphs,
+    frames: Option<PathBuf>,
+    gif: Option<gif::Encoder<BufWriter<File>>>,
+    every: u64,
+    ticks: u64,
+    saved: u64,
+}
+
+impl Recorder {
+    /// Records one frame every `every` ticks
+    pub fn new(frames: Option<&Path>, gif: Option<&Path>, every: u64) -> io::Result<Self> {
+        if let Some(dir) = frames {
+            fs::create_dir_all(dir)?;
+        }
+        let gif = match gif {
+            Some(path) => {
+                let [width, height] = WINDOW_SIZE.map(|size| size as u16);
+                let file = BufWriter::new(File::create(path)?);
+                let mut encoder =
+                    gif::Encoder::new(file, width, height, &[]).map_err(io::Error::other)?;
+                encoder
+                    .set_repeat(gif::Repeat::Infinite)
+                    .map_err(io::Error::other)?;
+                Some(encoder)
+            }
+            None => None,
+        };
+        Ok(Recorder {
+            glyphs: glyphs(),
+            frames: frames.map(Path::to_path_buf),
+            gif,
+            every: every.max(1),
+            ticks: 0,
+            saved: 0,
+        })
+    }
+
+    /// Frames saved so far
+    pub fn saved(&self) -> u64 {
+        self.saved
+    }
+
+    /// Called once a tick, saves a frame when one is due
+    pub fn record(&mut self, env: &Env, clock: &Clock) -> io::Result<()> {
+        let due = self.ticks.is_multiple_of(self.every);
+        self.ticks += 1;
+        if !due {
+            return Ok(());
+        }
+        let canvas = render(env, clock, &mut self.glyphs);
+        self.saved += 1;
+        if let Some(ref dir) = self.frames {
+            canvas.save_png(&dir.join(format!("frame-{:06}.png", self.saved)))?;
+        }
+        if let Some(ref mut encoder) = self.gif {
+            let (width, height) = (canvas.width() as u16, canvas.height() as u16);
+            let mut frame = gif::Frame::from_rgb_speed(width, height, canvas.as_rgb(), 30);
+            frame.delay = GIF_DELAY;
+            encoder.write_frame(&frame).map_err(io::Error::other)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        COLS, MAG,
+        config::Config,
+        game::{Game, GameMode},
+        map::Item,
+    };
+    use graphics::{Rectangle, Text};
+
+    #[test]
+    fn test_shapes_and_text() {
+        let mut canvas = Canvas::new(40, 20);
+        let ctx = canvas.context();
+        Rectangle::new([1.0, 0.0, 0.0, 1.0]).draw(
+            [0.0, 0.0, 10.0, 10.0],
+            &ctx.draw_state,
+            ctx.transform,
+            &mut canvas,
+        );
+        // half transparent, the shared diagonal isn't darker than the rest
+        Rectangle::new([0.0, 0.0, 0.0, 0.5]).draw(
+            [20.0, 0.0, 10.0, 10.0],
+            &ctx.draw_state,
+            ctx.transform,
+            &mut canvas,
+        );
+        assert_eq!(canvas.pixel(5, 5), [255, 0, 0]);
+        assert_eq!(canvas.pixel(15, 5), [255; 3]);
+        assert_eq!(canvas.pixel(24, 4), canvas.pixel(24, 6));
+        let mut glyphs = glyphs();
+        Text::new(12)
+            .draw_pos(
+                "W",
+                [2.0, 19.0],
+                &mut glyphs,
+                &ctx.draw_state,
+                ctx.transform,
+                &mut canvas,
+            )
+            .unwrap();
+        assert!((8..20).any(|y| (0..12).any(|x| canvas.pixel(x, y)[0] < 128)));
+    }
+
+    #[test]
+    fn test_render_and_record() {
+        let env = Env::new(Config {
+            seed: Some(2),
+            ..Config::default()
+        });
+        let clock = Clock::new(7);
+        let canvas = render(&env, &clock, &mut glyphs());
+        assert_eq!(canvas.width(), (f64::from(COLS) * MAG + 200.0) as u32);
+        // the first agent's square is outlined in its color
+        let l = env.agent_info()[0].location;
+        let cell = |c: u16| (f64::from(c) * MAG) as u32..(f64::from(c + 1) * MAG) as u32;
+        assert!(cell(l.row).any(|y| cell(l.col).any(|x| canvas.pixel(x, y) != [255; 3])));
+
+        let dir = std::env::temp_dir().join(format!("tileworld-frames-{}", std::process::id()));
+        let gif = dir.with_extension("gif");
+        let mut recorder = Recorder::new(Some(&dir), Some(&gif), 2).unwrap();
+        for _ in 0..3 {
+            recorder.record(&env, &clock).unwrap();
+        }
+        assert_eq!(recorder.saved(), 2);
+        drop(recorder);
+        assert!(dir.join("frame-000002.png").exists());
+        assert!(std::fs::read(&gif).unwrap().starts_with(b"GIF89a"));
+        std::fs::remove_dir_all(&dir).unwrap();
+        std::fs::remove_file(&gif).unwrap();
+    }
+
+    #[test]
+    fn test_render_after_editing_agents() {
+        let mut env = Env::new(Config {
+            seed: Some(3),
+            agents: 3,
+            game: Game {
+                mode: GameMode::Teams(2),
+                time_limit: None,
+            },
+            ..Config::default()
+        });
+        let clock = Clock::new(7);
+        for _ in 0..5 {
+            env.step_agents(&[]);
+        }
+        assert!(env.clear(env.agent_info()[2].location));
+        render(&env, &clock, &mut glyphs());
+        let free = env.grid().random_location().unwrap();
+        assert!(env.place(free, Item::Agent));
+        render(&env, &clock, &mut glyphs());
+        assert_eq!(env.history().len(), 1);
+    }
+}
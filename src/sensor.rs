@@ -0,0 +1,172 @@
+use crate::{COLS, ROWS, grid::Grid, location::Location};
+use std::str::FromStr;
+
+/// What an agent can perceive of the tiles and holes on the grid
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Sensor {
+    /// Everything, wherever it is
+    #[default]
+    Omniscient,
+    /// Only what is within `radius` cells. With `line_of_sight`, obstacles
+    /// also hide whatever is behind them.
+    Radius { radius: u16, line_of_sight: bool },
+}
+
+impl Sensor {
+    pub fn can_see(self, g: &Grid, from: Location, to: Location) -> bool {
+        match self {
+            Sensor::Omniscient => true,
+            Sensor::Radius {
+                radius,
+                line_of_sight,
+            } => {
+                let (col_diff, row_diff) = g.topology().offset(from, to);
+                let (col_diff, row_diff) = (u32::from(col_diff), u32::from(row_diff));
+                let radius = u32::from(radius);
+                col_diff * col_diff + row_diff * row_diff <= radius * radius
+                    && (!line_of_sight || line_of_sight_clear(g, from, to))
+            }
+        }
+    }
+}
+
+impl FromStr for Sensor {
+    type Err = String;
+
+    /// "all" for omniscient agents, "<radius>" or "<radius>:los" for a sensor
+    /// that is also blocked by obstacles
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "all" {
+            return Ok(Sensor::Omniscient);
+        }
+        let (radius, line_of_sight) = match s.strip_suffix(":los") {
+            Some(radius) => (radius, true),
+            None => (s, false),
+        };
+        let radius = radius
+            .parse()
+            .map_err(|_| format!("invalid sensor '{s}'"))?;
+        Ok(Sensor::Radius {
+            radius,
+            line_of_sight,
+        })
+    }
+}
+
+/// Walk the cells between `from` and `to` (Bresenham) and check none of them
+/// is an obstacle. The end points themselves never block the view. On a
+/// torus the line takes the short way around.
+fn line_of_sight_clear(g: &Grid, from: Location, to: Location) -> bool {
+    let (cols, rows) = (i32::from(COLS), i32::from(ROWS));
+    let (col_diff, row_diff) = g.topology().offset(from, to);
+    let (step_col, step_row) = g.topology().heading(from, to);
+    let (dc, dr) = (i32::from(col_diff), -i32::from(row_diff));
+    let (mut c, mut r) = (i32::from(from.col), i32::from(from.row));
+    let mut err = dc + dr;
+    for _ in 0..(dc - dr) {
+        let e2 = 2 * err;
+        if e2 >= dr {
+            err += dr;
+            c += step_col;
+        }
+        if e2 <= dc {
+            err += dc;
+            r += step_row;
+        }
+        let l = Location::new(c.rem_euclid(cols) as u16, r.rem_euclid(rows) as u16);
+        if l == to {
+            return true;
+        }
+        if g.is_obstacle(l) {
+            return false;
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::location::Topology;
+
+    const NEAR: Sensor = Sensor::Radius {
+        radius: 3,
+        line_of_sight: false,
+    };
+    const NEAR_LOS: Sensor = Sensor::Radius {
+        radius: 3,
+        line_of_sight: true,
+    };
+
+    #[test]
+    fn test_omniscient_sees_everything() {
+        let grid = Grid::new();
+        let far = Location::new(COLS - 1, ROWS - 1);
+        assert!(Sensor::Omniscient.can_see(&grid, Location::new(0, 0), far));
+    }
+
+    #[test]
+    fn test_radius() {
+        let grid = Grid::new();
+        let center = Location::new(10, 10);
+        assert!(NEAR.can_see(&grid, center, Location::new(13, 10)));
+        assert!(NEAR.can_see(&grid, center, Location::new(12, 12)));
+        assert!(!NEAR.can_see(&grid, center, Location::new(14, 10)));
+        assert!(!NEAR.can_see(&grid, center, Location::new(13, 12)));
+    }
+
+    #[test]
+    fn test_radius_wraps_on_torus() {
+        let mut grid = Grid::new();
+        let edge = Location::new(0, 5);
+        let other_side = Location::new(COLS - 2, 5);
+        assert!(!NEAR.can_see(&grid, edge, other_side));
+        grid.set_topology(Topology::Torus);
+        assert!(NEAR.can_see(&grid, edge, other_side));
+    }
+
+    #[test]
+    fn test_obstacle_blocks_line_of_sight() {
+        let mut grid = Grid::new();
+        let from = Location::new(5, 5);
+        let to = Location::new(8, 5);
+        grid.add_obstacle(Location::new(6, 5));
+        assert!(NEAR.can_see(&grid, from, to));
+        assert!(!NEAR_LOS.can_see(&grid, from, to));
+        // but the obstacle itself can be seen
+        assert!(NEAR_LOS.can_see(&grid, from, Location::new(6, 5)));
+        // and a cell beside it as well
+        assert!(NEAR_LOS.can_see(&grid, from, Location::new(5, 8)));
+    }
+
+    #[test]
+    fn test_line_of_sight_across_torus_edge() {
+        let mut grid = Grid::new();
+        grid.set_topology(Topology::Torus);
+        let from = Location::new(1, 5);
+        let to = Location::new(COLS - 1, 5);
+        assert!(NEAR_LOS.can_see(&grid, from, to));
+        grid.add_obstacle(Location::new(0, 5));
+        assert!(!NEAR_LOS.can_see(&grid, from, to));
+    }
+
+    #[test]
+    fn test_parse_sensor() {
+        assert_eq!("all".parse(), Ok(Sensor::Omniscient));
+        assert_eq!(
+            "4".parse(),
+            Ok(Sensor::Radius {
+                radius: 4,
+                line_of_sight: false
+            })
+        );
+        assert_eq!(
+            "4:los".parse(),
+            Ok(Sensor::Radius {
+                radius: 4,
+                line_of_sight: true
+            })
+        );
+        assert!("far".parse::<Sensor>().is_err());
+    }
+}
@@ -0,0 +1,316 @@
+use crate::{
+    env::Env,
+    external::Connection,
+    objects::Action,
+    protocol::{Hello, Percept, Reply, Update, World},
+};
+use log::{info, warn};
+use std::{
+    io,
+    net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    time::{Duration, Instant},
+};
+
+/// A client of the server, an agent or a spectator once it said hello
+struct Client {
+    stream: TcpStream,
+    connection: Connection,
+}
+
+impl Client {
+    fn new(stream: TcpStream, addr: SocketAddr, timeout: Duration) -> io::Result<Self> {
+        // the listener doesn't block, but talking to clients does, for as
+        // long as a client may take to answer
+        stream.set_nonblocking(false)?;
+        stream.set_nodelay(true)?;
+        stream.set_write_timeout(Some(timeout))?;
+        let connection = Connection::new(
+            format!("client {addr}"),
+            stream.try_clone()?,
+            stream.try_clone()?,
+        );
+        Ok(Client { stream, connection })
+    }
+
+    fn reply(&mut self, reply: &Reply) {
+        let line = serde_json::to_string(reply).expect("reply serializes") + "\n";
+        self.connection.send(&line);
+    }
+}
+
+impl Drop for Client {
+    fn drop(&mut self) {
+        // also stops the thread reading from it
+        let _ = self.stream.shutdown(Shutdown::Both);
+    }
+}
+
+/// Lets agents play and spectators watch over TCP. Clients start by saying
+/// hello (see `protocol::Hello`). Agents then get their percept and answer
+/// with an action every tick, like external agents do, while spectators get
+/// the world and then what changed after every tick.
+pub struct Server {
+    listener: TcpListener,
+    timeout: Duration,
+    // agents that clients may play
+    open: Vec<u8>,
+    pending: Vec<Client>,
+    agents: Vec<(u8, Client)>,
+    spectators: Vec<Client>,
+    // the world as the spectators last saw it
+    world: Option<World>,
+}
+
+impl Server {
+    /// Listen on `addr`. Remote agents get `timeout` to answer each tick and
+    /// can play the agents with the ids in `open`. Clients that take longer
+    /// than that to take in what is sent to them are dropped.
+    pub fn bind(addr: impl ToSocketAddrs, timeout: Duration, open: Vec<u8>) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        Ok(Server {
+            listener,
+            timeout,
+            open,
+            pending: vec![],
+            agents: vec![],
+            spectators: vec![],
+            world: None,
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Take in new clients and answer the ones that said hello
+    pub fn accept(&mut self, env: &Env) {
+        loop {
+            match self.listener.accept() {
+                Ok((stream, addr)) => match Client::new(stream, addr, self.timeout) {
+                    Ok(client) => self.pending.push(client),
+                    Err(e) => warn!("client {addr}: {e}"),
+                },
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    warn!("cannot accept clients: {e}");
+                    break;
+                }
+            }
+        }
+        for mut client in std::mem::take(&mut self.pending) {
+            let Some(line) = client.connection.try_receive() else {
+                if client.connection.is_alive() {
+                    self.pending.push(client);
+                }
+                continue;
+            };
+            match serde_json::from_str(&line) {
+                Ok(Hello::Agent { id }) => {
+                    if !self.open.contains(&id) {
+                        let message = format!("agent {id} can't be played remotely");
+                        client.reply(&Reply::Error { message });
+                    } else if self.agents.iter().any(|&(a, _)| a == id) {
+                        let message = format!("agent {id} is already played");
+                        client.reply(&Reply::Error { message });
+                    } else {
+                        info!("agent {id} joined");
+                        client.connection.set_label(format!("agent {id}"));
+                        client.reply(&Reply::Welcome);
+                        self.agents.push((id, client));
+                    }
+                }
+                Ok(Hello::Spectator) => {
+                    let world = World::new(env);
+                    client.reply(&Reply::Welcome);
+                    client
+                        .connection
+                        .send(&Update::Snapshot(world.clone()).to_line());
+                    self.world.get_or_insert(world);
+                    self.spectators.push(client);
+                }
+                Err(e) => {
+                    let message = format!("invalid hello '{line}': {e}");
+                    client.reply(&Reply::Error { message });
+                }
+            }
+        }
+    }
+
+    /// The action of every agent in `env` for this tick, None for agents that
+    /// aren't played by a client
+    pub fn actions(&mut self, env: &Env) -> Vec<Option<Action>> {
+        let mut actions = vec![None; env.agent_info().len()];
+        for (id, client) in &mut self.agents {
+            if let Some(info) = env.agent_info().iter().find(|a| a.id == *id) {
+                client.connection.request(&Percept::new(env, info));
+            }
+        }
+        let deadline = Instant::now() + self.timeout;
+        for (id, client) in &mut self.agents {
+            if let Some(slot) = env.agent_slot(*id).and_then(|i| actions.get_mut(i)) {
+                *slot = client.connection.action(deadline);
+            }
+        }
+        self.agents.retain(|(id, client)| {
+            let alive = client.connection.is_alive();
+            if !alive {
+                warn!("agent {id}: the built-in planner takes over");
+            }
+            alive
+        });
+        actions
+    }
+
+    /// Tell the spectators what changed since the last time
+    pub fn publish(&mut self, env: &Env) {
+        let world = World::new(env);
+        let update = match self.world {
+            Some(ref previous) if previous.episode == world.episode => {
+                Update::Diff(previous.diff(&world))
+            }
+            _ => Update::Snapshot(world.clone()),
+        };
+        let line = update.to_line();
+        for spectator in &mut self.spectators {
+            spectator.connection.send(&line);
+        }
+        self.spectators.retain(|s| s.connection.is_alive());
+        self.world = Some(world);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::Config, location::Direction, protocol::action_line};
+    use std::io::{BufRead, BufReader, Write};
+
+    fn connect(server: &Server, hello: &str) -> (TcpStream, BufReader<TcpStream>) {
+        let stream = TcpStream::connect(server.local_addr().unwrap()).unwrap();
+        let reader = BufReader::new(stream.try_clone().unwrap());
+        (&stream)
+            .write_all(format!("{hello}\n").as_bytes())
+            .unwrap();
+        (stream, reader)
+    }
+
+    fn read_line(reader: &mut BufReader<TcpStream>) -> String {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        line
+    }
+
+    /// Accept until the server has answered everyone
+    fn accept_all(server: &mut Server, env: &Env) {
+        for _ in 0..100 {
+            server.accept(env);
+            if server.pending.is_empty() {
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        panic!("clients never said hello");
+    }
+
+    fn setup() -> (Env, Server) {
+        let env = Env::new(Config {
+            agents: 3,
+            ..Config::default()
+        });
+        let server = Server::bind("127.0.0.1:0", Duration::from_secs(2), vec![2, 3]).unwrap();
+        (env, server)
+    }
+
+    #[test]
+    fn test_agents_authenticate() {
+        let (env, mut server) = setup();
+        let (_a, mut a) = connect(&server, r#"{"role": "agent", "id": 2}"#);
+        let (_b, mut b) = connect(&server, r#"{"role": "agent", "id": 1}"#);
+        accept_all(&mut server, &env);
+        let (_c, mut c) = connect(&server, r#"{"role": "agent", "id": 2}"#);
+        let (_d, mut d) = connect(&server, "hello");
+        accept_all(&mut server, &env);
+        assert_eq!(
+            serde_json::from_str::<Reply>(&read_line(&mut a)).unwrap(),
+            Reply::Welcome
+        );
+        for reader in [&mut b, &mut c, &mut d] {
+            let reply = serde_json::from_str::<Reply>(&read_line(reader)).unwrap();
+            assert!(matches!(reply, Reply::Error { .. }));
+        }
+        assert_eq!(server.agents.len(), 1);
+    }
+
+    #[test]
+    fn test_remote_agent_plays() {
+        let (mut env, mut server) = setup();
+        let (stream, mut reader) = connect(&server, r#"{"role": "agent", "id": 3}"#);
+        accept_all(&mut server, &env);
+        read_line(&mut reader);
+        let client = std::thread::spawn(move || {
+            for _ in 0..3 {
+                let percept: Percept = serde_json::from_str(&read_line(&mut reader)).unwrap();
+                assert_eq!(percept.id, 3);
+                (&stream)
+                    .write_all(action_line(Action::Move(Direction::Left)).as_bytes())
+                    .unwrap();
+            }
+        });
+        for _ in 0..3 {
+            let actions = server.actions(&env);
+            assert_eq!(
+                actions,
+                vec![None, None, Some(Action::Move(Direction::Left))]
+            );
+            env.step_agents(&actions);
+        }
+        client.join().unwrap();
+        // the client is gone, agent 3 is free again
+        assert_eq!(server.actions(&env), vec![None, None, None]);
+        assert!(server.agents.is_empty());
+    }
+
+    #[test]
+    fn test_spectator_that_stops_reading_is_dropped() {
+        let (env, mut server) = setup();
+        let (_stream, _reader) = connect(&server, r#"{"role": "spectator"}"#);
+        accept_all(&mut server, &env);
+        // fills the socket buffers, then a write times out
+        let line = "x".repeat(1 << 20) + "\n";
+        let start = Instant::now();
+        while !server.spectators.is_empty() {
+            assert!(
+                start.elapsed() < Duration::from_secs(60),
+                "spectator never dropped"
+            );
+            server.spectators[0].connection.send(&line);
+            server.spectators.retain(|s| s.connection.is_alive());
+        }
+    }
+
+    #[test]
+    fn test_spectators_follow_the_world() {
+        let (mut env, mut server) = setup();
+        let (_stream, mut reader) = connect(&server, r#"{"role": "spectator"}"#);
+        accept_all(&mut server, &env);
+        assert_eq!(
+            serde_json::from_str::<Reply>(&read_line(&mut reader)).unwrap(),
+            Reply::Welcome
+        );
+        let Ok(Update::Snapshot(mut world)) = serde_json::from_str(&read_line(&mut reader)) else {
+            panic!("no snapshot");
+        };
+        for _ in 0..10 {
+            env.step(&[]);
+            server.publish(&env);
+        }
+        for _ in 0..10 {
+            let Ok(Update::Diff(diff)) = serde_json::from_str(&read_line(&mut reader)) else {
+                panic!("no diff");
+            };
+            world.apply(&diff);
+        }
+        assert_eq!(world, World::new(&env));
+    }
+}
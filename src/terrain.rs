@@ -0,0 +1,55 @@
+use graphics::types::Color;
+
+/// Ground type of a cell. Entering a cell costs `cost()` ticks, both for the
+/// agents moving over it and for the path planner.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Terrain {
+    #[default]
+    Road,
+    Grass,
+    Mud,
+    Water,
+}
+
+pub const TERRAINS: [Terrain; 4] = [Terrain::Road, Terrain::Grass, Terrain::Mud, Terrain::Water];
+
+impl Terrain {
+    pub fn cost(self) -> u32 {
+        match self {
+            Terrain::Road => 1,
+            Terrain::Grass => 2,
+            Terrain::Mud => 3,
+            Terrain::Water => 5,
+        }
+    }
+
+    /// Background color of the cell, None for plain road
+    pub fn color(self) -> Option<Color> {
+        match self {
+            Terrain::Road => None,
+            Terrain::Grass => Some([0.8, 0.95, 0.75, 1.]),
+            Terrain::Mud => Some([0.85, 0.75, 0.6, 1.]),
+            Terrain::Water => Some([0.7, 0.85, 1., 1.]),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_road_is_cheapest() {
+        for t in TERRAINS {
+            assert!(t.cost() >= Terrain::Road.cost());
+        }
+        assert_eq!(Terrain::default(), Terrain::Road);
+        assert_eq!(Terrain::Road.cost(), 1);
+    }
+
+    #[test]
+    fn test_costs_increase() {
+        assert!(Terrain::Grass.cost() < Terrain::Mud.cost());
+        assert!(Terrain::Mud.cost() < Terrain::Water.cost());
+    }
+}
@@ -0,0 +1,222 @@
+use crate::{
+    COLS, ROWS,
+    clock::Clock,
+    draw::{get_color, obstacle_color, team_color},
+    env::Env,
+    game::{GameMode, team_scores},
+    location::Location,
+    objects::GO,
+};
+use crossterm::{
+    cursor, event,
+    event::{Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    execute, queue,
+    style::{self, Stylize},
+    terminal,
+};
+use graphics::types::Color;
+use piston::Key;
+use std::{
+    io::{self, Stdout, Write},
+    time::Instant,
+};
+
+/// What a key pressed in the terminal asks for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    /// Handed to the clock, which pauses, steps and sets the speed
+    Clock(Key),
+    Quit,
+}
+
+/// Space pauses and resumes, N steps, + and - change the speed like in the
+/// window, and Q, Esc or Ctrl-C quit
+pub fn command(key: KeyEvent) -> Option<Command> {
+    let command = match key.code {
+        KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => Command::Quit,
+        KeyCode::Char('q') | KeyCode::Esc => Command::Quit,
+        KeyCode::Char(' ') => Command::Clock(Key::Space),
+        KeyCode::Char('n') => Command::Clock(Key::N),
+        KeyCode::Char('+' | '=') => Command::Clock(Key::Equals),
+        KeyCode::Char('-') => Command::Clock(Key::Minus),
+        _ => return None,
+    };
+    Some(command)
+}
+
+fn rgb([r, g, b, _]: Color) -> style::Color {
+    let [r, g, b] = [r, g, b].map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8);
+    style::Color::Rgb { r, g, b }
+}
+
+/// Two characters for the cell at `l`, so cells come out about square
+fn cell(env: &Env, l: Location) -> String {
+    let g = env.grid();
+    let background = rgb(g.terrain(l).color().unwrap_or([1.0; 4]));
+    let black = style::Color::Black;
+    let (text, color) = match g.object(l).map(|o| o.borrow()) {
+        None => ("  ".to_string(), black),
+        Some(o) => match *o {
+            GO::Obstacle(ref o) => ("██".to_string(), rgb(obstacle_color(o.kind))),
+            GO::Hole(_) => (" ●".to_string(), black),
+            GO::Tile(ref t) => (format!(" {}", t.score), black),
+            GO::Agent(ref a) => {
+                let carried = match a.tile {
+                    Some(ref t) if a.has_tile => t.borrow().score().to_string(),
+                    _ => " ".to_string(),
+                };
+                (format!("@{carried}"), rgb(get_color(a.id - 1)))
+            }
+        },
+    };
+    text.with(color).on(background).bold().to_string()
+}
+
+/// The panel to the right of the grid: scores, the episode and the keys
+fn panel(env: &Env, clock: &Clock) -> Vec<String> {
+    let (agents, game, episode) = (env.agent_info(), env.game(), env.episode());
+    let mut lines = vec![];
+    for agent in agents {
+        let text = format!("Agent({}): {}", agent.id, agent.score);
+        lines.push(text.with(rgb(get_color(agent.id - 1))).to_string());
+    }
+    lines.push(String::new());
+    if matches!(game.mode, GameMode::Teams(_)) {
+        for (team, score) in team_scores(agents) {
+            lines.push(
+                format!("Team {team}: {score}")
+                    .with(rgb(team_color(team)))
+                    .to_string(),
+            );
+        }
+        lines.push(String::new());
+    }
+    lines.push(format!(
+        "Episode {}, tick {}",
+        episode.number,
+        env.grid().tick()
+    ));
+    lines.push(clock.status());
+    if let Some(reason) = episode.ended() {
+        lines.push(format!("Ended: {reason}"));
+        lines.push(game.result(agents));
+    } else if let Some(limit) = game.time_limit {
+        lines.push(format!(
+            "Ticks left: {}",
+            limit.saturating_sub(env.grid().tick())
+        ));
+    }
+    lines.push(String::new());
+    lines.push("Space pauses, N steps,".to_string());
+    lines.push("+ and - change the speed,".to_string());
+    lines.push("Q quits".to_string());
+    lines
+}
+
+/// The grid row by row, with the panel next to it
+pub fn lines(env: &Env, clock: &Clock) -> Vec<String> {
+    let mut panel = panel(env, clock).into_iter();
+    (0..ROWS)
+        .map(|row| {
+            let cells: String = (0..COLS)
+                .map(|col| cell(env, Location::new(col, row)))
+                .collect();
+            format!("{cells}  {}", panel.next().unwrap_or_default())
+        })
+        .collect()
+}
+
+/// The terminal in raw mode on its alternate screen, restored when dropped
+pub struct Terminal {
+    out: Stdout,
+}
+
+impl Terminal {
+    pub fn start() -> io::Result<Self> {
+        let mut out = io::stdout();
+        terminal::enable_raw_mode()?;
+        execute!(
+            out,
+            terminal::EnterAlternateScreen,
+            cursor::Hide,
+            terminal::Clear(terminal::ClearType::All)
+        )?;
+        Ok(Terminal { out })
+    }
+
+    pub fn draw(&mut self, lines: &[String]) -> io::Result<()> {
+        queue!(self.out, cursor::MoveTo(0, 0))?;
+        for line in lines {
+            queue!(
+                self.out,
+                style::Print(line),
+                terminal::Clear(terminal::ClearType::UntilNewLine),
+                cursor::MoveToNextLine(1)
+            )?;
+        }
+        self.out.flush()
+    }
+
+    /// Wait until `deadline` for a key press, returning early with what it
+    /// asks for
+    pub fn poll(&mut self, deadline: Instant) -> io::Result<Option<Command>> {
+        while event::poll(deadline.saturating_duration_since(Instant::now()))? {
+            if let Event::Key(key) = event::read()?
+                && key.kind == KeyEventKind::Press
+                && let Some(command) = command(key)
+            {
+                return Ok(Some(command));
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        let _ = execute!(self.out, cursor::Show, terminal::LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::Config,
+        map::{Item, Map},
+    };
+
+    #[test]
+    fn test_keys() {
+        let key = |code| command(KeyEvent::new(code, KeyModifiers::NONE));
+        assert_eq!(key(KeyCode::Char(' ')), Some(Command::Clock(Key::Space)));
+        assert_eq!(key(KeyCode::Char('+')), Some(Command::Clock(Key::Equals)));
+        assert_eq!(key(KeyCode::Esc), Some(Command::Quit));
+        assert_eq!(key(KeyCode::Char('x')), None);
+        let ctrl_c = KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL);
+        assert_eq!(command(ctrl_c), Some(Command::Quit));
+    }
+
+    #[test]
+    fn test_lines() {
+        let mut map = Map::new();
+        map.set(Location::new(0, 0), Item::Obstacle);
+        map.set(Location::new(2, 0), Item::Tile(7));
+        map.set(Location::new(4, 0), Item::Hole);
+        map.set(Location::new(6, 1), Item::Agent);
+        let env = Env::new(Config {
+            map: Some(map),
+            agents: 1,
+            ..Config::default()
+        });
+        let lines = lines(&env, &Clock::new(7));
+        assert_eq!(lines.len(), usize::from(ROWS));
+        assert!(lines[0].contains("██"));
+        assert!(lines[0].contains(" 7"));
+        assert!(lines[0].contains(" ●"));
+        assert!(lines[0].ends_with(&"Agent(1): 0".with(rgb(get_color(0))).to_string()));
+        assert!(lines[1].contains("@ "));
+        assert!(lines[3].ends_with("Speed: 7 ticks/s"));
+    }
+}
@@ -0,0 +1,179 @@
+use crate::{COLS, MAG, ROWS, location::Location};
+use graphics::{Context, Transformed};
+
+/// Width of the sidebar on the right edge of the window
+pub const SIDEBAR: f64 = 200.0;
+
+/// Size the window opens at: the grid at `MAG` pixels per cell and the sidebar
+pub const WINDOW_SIZE: [f64; 2] = [COLS as f64 * MAG + SIDEBAR, ROWS as f64 * MAG];
+
+/// Smallest and largest zoom, in pixels per cell
+const ZOOM_RANGE: (f64, f64) = (2.0, 120.0);
+/// How much one step of the mouse wheel zooms
+const ZOOM_STEP: f64 = 1.25;
+
+/// Which part of the grid the window shows, and how large. The grid is drawn
+/// in units of `MAG` pixels per cell and scaled to the zoom, the sidebar keeps
+/// its size on the right edge of the window.
+#[derive(Debug, Clone, PartialEq)]
+pub struct View {
+    size: [f64; 2],
+    /// Pixels per cell
+    zoom: f64,
+    /// Where the top left corner of the grid is in the window
+    offset: [f64; 2],
+    /// Where the mouse was when a pan started, and the offset then
+    drag: Option<([f64; 2], [f64; 2])>,
+}
+
+impl View {
+    /// A view of the whole grid in a window of `size`
+    pub fn new(size: [f64; 2]) -> Self {
+        let mut view = View {
+            size,
+            zoom: MAG,
+            offset: [0.0; 2],
+            drag: None,
+        };
+        view.fit();
+        view
+    }
+
+    pub fn size(&self) -> [f64; 2] {
+        self.size
+    }
+
+    pub fn zoom(&self) -> f64 {
+        self.zoom
+    }
+
+    /// Scale the grid to fill the space left of the sidebar and center it
+    pub fn fit(&mut self) {
+        let [width, height] = [(self.size[0] - SIDEBAR).max(0.0), self.size[1]];
+        let (min, max) = ZOOM_RANGE;
+        self.zoom = (width / f64::from(COLS))
+            .min(height / f64::from(ROWS))
+            .clamp(min, max);
+        self.offset = [
+            (width - self.zoom * f64::from(COLS)) / 2.0,
+            (height - self.zoom * f64::from(ROWS)) / 2.0,
+        ];
+    }
+
+    /// The window changed size, the grid is fitted into it again
+    pub fn resize(&mut self, size: [f64; 2]) {
+        self.size = size;
+        self.fit();
+    }
+
+    /// Zoom in (positive `steps`) or out around `point`, which stays over
+    /// the same spot of the grid
+    pub fn zoom_at(&mut self, point: [f64; 2], steps: f64) {
+        let (min, max) = ZOOM_RANGE;
+        let zoom = (self.zoom * ZOOM_STEP.powf(steps)).clamp(min, max);
+        let scale = zoom / self.zoom;
+        self.offset = [0, 1].map(|i| point[i] - (point[i] - self.offset[i]) * scale);
+        self.zoom = zoom;
+    }
+
+    /// Start dragging the grid around with the mouse at `point`
+    pub fn start_drag(&mut self, point: [f64; 2]) {
+        self.drag = Some((point, self.offset));
+    }
+
+    /// The mouse moved to `point`. Returns whether that moved the grid.
+    pub fn drag_to(&mut self, point: [f64; 2]) -> bool {
+        let Some((start, offset)) = self.drag else {
+            return false;
+        };
+        self.offset = [0, 1].map(|i| offset[i] + point[i] - start[i]);
+        true
+    }
+
+    pub fn end_drag(&mut self) {
+        self.drag = None;
+    }
+
+    /// For drawing the grid in units of `MAG` pixels per cell, clipped to
+    /// the space left of the sidebar
+    pub fn grid(&self, ctx: &Context) -> Context {
+        let mut grid = ctx
+            .trans(self.offset[0], self.offset[1])
+            .zoom(self.zoom / MAG);
+        // the scissor is in pixels of the framebuffer, which may be larger
+        // than the window on high resolution screens
+        let scale = ctx
+            .viewport
+            .map_or(1.0, |v| f64::from(v.draw_size[0]) / v.window_size[0]);
+        let [width, height] =
+            [(self.size[0] - SIDEBAR).max(0.0), self.size[1]].map(|s| (s * scale) as u32);
+        grid.draw_state = grid.draw_state.scissor([0, 0, width, height]);
+        grid
+    }
+
+    /// For drawing the sidebar, from its top left corner
+    pub fn sidebar(&self, ctx: &Context) -> Context {
+        ctx.trans(self.size[0] - SIDEBAR, 0.0)
+    }
+
+    /// The cell under a point in the window, if there is one
+    pub fn cell_at(&self, [x, y]: [f64; 2]) -> Option<Location> {
+        if x >= self.size[0] - SIDEBAR {
+            return None;
+        }
+        let [col, row] = [
+            (x - self.offset[0]) / self.zoom,
+            (y - self.offset[1]) / self.zoom,
+        ];
+        if col < 0.0 || row < 0.0 {
+            return None;
+        }
+        let (c, r) = (col as u16, row as u16);
+        (c < COLS && r < ROWS).then(|| Location::new(c, r))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fit_and_resize() {
+        let mut view = View::new(WINDOW_SIZE);
+        assert_eq!(view.zoom(), MAG);
+        assert_eq!(
+            view.cell_at([MAG * 3.5, MAG * 2.5]),
+            Some(Location::new(3, 2))
+        );
+        assert_eq!(view.cell_at([WINDOW_SIZE[0] - 10.0, 10.0]), None);
+        // twice as high, the width limits the zoom and the grid is centered
+        view.resize([WINDOW_SIZE[0], 2.0 * WINDOW_SIZE[1]]);
+        assert_eq!(view.zoom(), MAG);
+        assert_eq!(view.cell_at([1.0, 1.0]), None);
+        assert_eq!(
+            view.cell_at([1.0, WINDOW_SIZE[1] / 2.0 + 1.0]),
+            Some(Location::new(0, 0))
+        );
+    }
+
+    #[test]
+    fn test_zoom_and_pan() {
+        let mut view = View::new(WINDOW_SIZE);
+        let point = [MAG * 10.5, MAG * 10.5];
+        view.zoom_at(point, 2.0);
+        assert!(view.zoom() > MAG);
+        assert_eq!(view.cell_at(point), Some(Location::new(10, 10)));
+        view.zoom_at(point, -100.0);
+        assert_eq!(view.zoom(), ZOOM_RANGE.0);
+
+        view.fit();
+        view.start_drag([100.0, 100.0]);
+        assert!(view.drag_to([100.0 + MAG, 100.0]));
+        view.end_drag();
+        assert!(!view.drag_to([0.0, 0.0]));
+        assert_eq!(
+            view.cell_at([MAG * 3.5, MAG * 2.5]),
+            Some(Location::new(2, 2))
+        );
+    }
+}